@@ -0,0 +1,95 @@
+// Per-frame CPU cost of looking up the views of every G-buffer layer, cached by the
+// G-buffer against creating them on every call as the passes used to.
+//
+//     cargo bench --bench layer_views
+
+use std::time::{Duration, Instant};
+
+use defered::renderer::gbuffer::GBuffer;
+use wgpu::{
+    Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension,
+};
+
+const FRAMES: u32 = 1000;
+const SIZE: u32 = 256;
+
+fn main() {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+        .expect("no adapter to benchmark on");
+    let (device, _queue) = pollster::block_on(adapter.request_device(&Default::default())).unwrap();
+    println!("adapter: {}", adapter.get_info().name);
+
+    for layers in [4, 16, 64] {
+        let gbuffer = GBuffer::new(
+            &device,
+            SIZE,
+            SIZE,
+            layers,
+            TextureFormat::Rgba8UnormSrgb,
+            1,
+        );
+        let cached = time(|| {
+            for layer in 0..layers {
+                std::hint::black_box(gbuffer.layer_views(layer).color_attachments());
+            }
+        });
+
+        // The same five targets, with views made on demand
+        let textures = [
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba8Unorm,
+            TextureFormat::Rgba16Float,
+            TextureFormat::R32Uint,
+            TextureFormat::Depth24Plus,
+        ]
+        .map(|format| create_array(&device, format, layers));
+        let created = time(|| {
+            for layer in 0..layers {
+                for (name, texture) in ["Color", "Normal", "Emissive", "Id", "Depth"]
+                    .iter()
+                    .zip(&textures)
+                {
+                    std::hint::black_box(texture.create_view(&TextureViewDescriptor {
+                        label: Some(&format!("GBuffer {} Layer {}", name, layer)),
+                        dimension: Some(TextureViewDimension::D2),
+                        base_array_layer: layer,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    }));
+                }
+            }
+        });
+
+        println!(
+            "{:>2} layers: created {:>8.2?} per frame, cached {:>8.2?} per frame",
+            layers, created, cached
+        );
+    }
+}
+
+fn time(mut frame: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame();
+    }
+    start.elapsed() / FRAMES
+}
+
+fn create_array(device: &Device, format: TextureFormat, layers: u32) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
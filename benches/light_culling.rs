@@ -0,0 +1,119 @@
+// Per-frame cost of lighting every G-buffer layer with thousands of small point lights,
+// binned into screen tiles first so each pixel only shades the lights of its tile, next to
+// looping every light for every pixel. Nothing occludes, so no shadows are traced. Runs on
+// the software adapter to stay comparable between machines.
+//
+//     cargo bench --bench light_culling
+
+use std::time::{Duration, Instant};
+
+use defered::renderer::{
+    camera::Camera,
+    cookie::Cookies,
+    gbuffer::GBuffer,
+    light::{LayerLighting, Light},
+    mesh::Meshes,
+    particles::Emitters,
+    passes::{Lighting, RenderPassData, Shadows},
+    texture::Textures,
+    tilemap::Tilemaps,
+};
+use wgpu::{PollType, RequestAdapterOptions, TextureFormat};
+
+const FRAMES: u32 = 20;
+// Looping every light for every pixel takes up to minutes a frame on the software adapter
+const NAIVE_FRAMES: u32 = 1;
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 360;
+const LAYERS: u32 = 4;
+
+fn main() {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
+        force_fallback_adapter: true,
+        ..Default::default()
+    }))
+    .expect("no software adapter to benchmark on");
+    let (device, queue) = pollster::block_on(adapter.request_device(&Default::default())).unwrap();
+    println!("adapter: {}", adapter.get_info().name);
+
+    let gbuffer = GBuffer::new(
+        &device,
+        WIDTH,
+        HEIGHT,
+        LAYERS,
+        TextureFormat::Rgba8UnormSrgb,
+        1,
+    );
+    let mut culled = Lighting::new(&device, &gbuffer, true);
+    let mut naive = Lighting::new(&device, &gbuffer, false);
+    // Bound by the lighting but never read without occluders
+    let shadows = Shadows::new(&device, 1);
+    let layers = vec![LayerLighting::default(); LAYERS as usize];
+    let camera = Camera::new([0.0, 0.0], [WIDTH as f32, HEIGHT as f32]);
+    let (textures, meshes, tilemaps, emitters) = (
+        Textures::default(),
+        Meshes::default(),
+        Tilemaps::default(),
+        Emitters::default(),
+    );
+    let cookies = Cookies::new(&device);
+
+    for count in [1_000, 10_000] {
+        let lights = scatter(count);
+        let frame = |lighting: &mut Lighting, lights: &[Light]| {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let mut rpd = RenderPassData {
+                gbuffer: &gbuffer,
+                encoder: &mut encoder,
+                device: &device,
+                queue: &queue,
+                textures: &textures,
+                meshes: &meshes,
+                tilemaps: &tilemaps,
+                emitters: &emitters,
+                cookies: &cookies,
+            };
+            lighting.prepare(&mut rpd, lights, &layers, &camera);
+            for layer in 0..LAYERS {
+                lighting.execute(&mut rpd, layer, shadows.view());
+            }
+            queue.submit(Some(encoder.finish()));
+            device.poll(PollType::Wait).unwrap();
+        };
+        let culled_time = time(FRAMES, &lights, |lights| frame(&mut culled, lights));
+        let naive_time = time(NAIVE_FRAMES, &lights, |lights| frame(&mut naive, lights));
+        println!(
+            "{:>6} lights: {:>8.2?} culled, {:>8.2?} naive per frame",
+            count, culled_time, naive_time
+        );
+    }
+}
+
+// Small lights spread over the view like bullets, placed by a fixed LCG so every run
+// lights the same scene
+fn scatter(count: u32) -> Vec<Light> {
+    let mut seed = 1u32;
+    let mut random = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 8) as f32 / (1 << 24) as f32
+    };
+    (0..count)
+        .map(|_| Light {
+            pos: [random() * WIDTH as f32, random() * HEIGHT as f32],
+            radius: 8.0 + random() * 16.0,
+            color: [random(), random(), random()],
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn time(frames: u32, lights: &[Light], mut frame: impl FnMut(&[Light])) -> Duration {
+    // The first frame compiles the pipelines, a single light keeps it short
+    frame(&lights[..1]);
+    let start = Instant::now();
+    for _ in 0..frames {
+        frame(lights);
+    }
+    start.elapsed() / frames
+}
@@ -0,0 +1,2 @@
+// Layered 2D deferred renderer, driven by the demo in main.rs and by the benches
+pub mod renderer;
@@ -1,12 +1,12 @@
-mod renderer;
-
 use std::{
+    pin::Pin,
     sync::Arc,
+    task::{Context, Poll, Waker},
     time::{Duration, Instant},
 };
 use winit::{
     application::ApplicationHandler,
-    event::{ElementState, WindowEvent},
+    event::{ElementState, MouseButton, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
     keyboard::Key,
     platform::modifier_supplement::KeyEventExtModifierSupplement,
@@ -14,10 +14,43 @@ use winit::{
 };
 
 const TARGET_FPS: f32 = 60.0;
+// Light masks, the ground only gets the sky while the gameplay layers get everything
+const SKY_LIGHT: u32 = 1;
+const DYNAMIC_LIGHTS: u32 = 2;
+
+use defered::renderer::{
+    camera::Camera,
+    cookie::CookieId,
+    light::{GradientId, LayerLighting, Light},
+    material::{MaterialDescriptor, MaterialId, DEFAULT_MATERIAL},
+    mesh::{MeshDraw, MeshId, Transform, Vertex},
+    object::{Object, OBJECT_FLIP_X, OBJECT_FLIP_Y, OBJECT_HIDDEN, OBJECT_SCREEN_SPACE},
+    particles::{EmitterDescriptor, EmitterId},
+    texture::TextureId,
+    tilemap::{TileAnimation, TilemapDescriptor, TilemapId, TILE_FLIP_X, TILE_FLIP_Y, TILE_ROTATE},
+    Renderer,
+};
+// Objects under the cursor or in the dragged box, as (layer, index)
+type Selection = Pin<Box<dyn Future<Output = Vec<(u32, u32)>>>>;
 
-use renderer::{camera::Camera, object::Object, Renderer};
 struct App {
     renderer: Option<Renderer>,
+    stripes: MaterialId,
+    checker: MaterialId,
+    bumps: Option<TextureId>,
+    terrain: Option<MeshId>,
+    ground: Option<TilemapId>,
+    fountain: Option<EmitterId>,
+    window_cookie: Option<CookieId>,
+    flicker: Option<GradientId>,
+    show_marker: bool,
+    cursor: [u32; 2],
+    drag_start: Option<[u32; 2]>,
+    // Objects last picked or selected
+    selected: Vec<(u32, u32)>,
+    // Pick or selection still being read back, replaces `selected` once done
+    selecting: Option<Selection>,
+    start: Instant,
     last_frame: Instant,
     frame_time: Duration,
     camera: Camera,
@@ -27,6 +60,20 @@ impl Default for App {
     fn default() -> Self {
         Self {
             renderer: None,
+            stripes: DEFAULT_MATERIAL,
+            checker: DEFAULT_MATERIAL,
+            bumps: None,
+            terrain: None,
+            ground: None,
+            fountain: None,
+            window_cookie: None,
+            flicker: None,
+            show_marker: true,
+            cursor: [0, 0],
+            drag_start: None,
+            selected: Vec::new(),
+            selecting: None,
+            start: Instant::now(),
             camera: Camera::new([0.0, 0.0], [800.0, 600.0]),
             last_frame: Instant::now(),
             frame_time: Duration::from_secs_f32(1.0 / TARGET_FPS),
@@ -40,7 +87,103 @@ impl ApplicationHandler for App {
 
         let window = Arc::new(event_loop.create_window(window).unwrap());
 
-        let renderer = pollster::block_on(Renderer::new(window.clone()));
+        let mut renderer = pollster::block_on(Renderer::new(window.clone()));
+        self.stripes = renderer.register_material(&MaterialDescriptor {
+            label: "Stripes",
+            source: include_str!("shaders/stripes.wgsl"),
+            uniform: Some(bytemuck::cast_slice(&[8.0f32, 0.5, 0.0, 0.0])),
+            textures: &[],
+        });
+
+        let checker: Vec<u8> = (0..64)
+            .flat_map(|i| {
+                let v = if (i % 8 + i / 8) % 2 == 0 { 255 } else { 64 };
+                [v, v, v, 255]
+            })
+            .collect();
+        let checker = renderer.create_texture(8, 8, &checker);
+        self.checker = renderer.register_material(&MaterialDescriptor {
+            label: "Checker",
+            source: include_str!("shaders/textured.wgsl"),
+            uniform: None,
+            textures: &[checker],
+        });
+
+        self.bumps = Some(renderer.create_linear_texture(BUMPS_SIZE, BUMPS_SIZE, &bumps()));
+
+        let (vertices, indices) = terrain(0.0);
+        self.terrain = Some(renderer.create_mesh(&vertices, &indices));
+
+        let tileset = renderer.create_texture(TILESET_COLUMNS * 8, 8, &tileset());
+        let tiles: Vec<u32> = (0..40 * 30)
+            .map(|i: u32| match i.wrapping_mul(2654435761) >> 28 {
+                0 => 2 | TILE_ROTATE,
+                1 => 2 | TILE_FLIP_X,
+                2 => 2 | TILE_FLIP_Y,
+                _ => 1,
+            })
+            .collect();
+        let ground = renderer.create_tilemap(&TilemapDescriptor {
+            pos: [0.0, 0.0],
+            tile_size: [20.0, 20.0],
+            width: 40,
+            height: 30,
+            tiles: &tiles,
+            tileset,
+            tileset_columns: TILESET_COLUMNS,
+            tileset_rows: 1,
+            animations: &[TileAnimation {
+                tile: 2,
+                frames: 2,
+                frame_time: 0.5,
+            }],
+            // Behind everything else drawn into the layer
+            depth: 0.75,
+        });
+        renderer.update_tiles(ground, [10, 5], [6, 4], &[3; 24]);
+        self.ground = Some(ground);
+
+        self.fountain = Some(renderer.create_emitter(2, 4096, &fountain_emitter(0.0)));
+        self.window_cookie =
+            Some(renderer.create_cookie(WINDOW_SIZE, WINDOW_SIZE, &window_frame()));
+        self.flicker = Some(renderer.create_gradient(&[
+            (0.0, [1.0, 1.0, 1.0]),
+            (0.2, [0.7, 0.6, 0.5]),
+            (0.35, [1.0, 0.9, 0.8]),
+            (0.6, [0.6, 0.5, 0.4]),
+            (0.8, [0.9, 0.85, 0.8]),
+            (1.0, [1.0, 1.0, 1.0]),
+        ]));
+
+        renderer.set_layer_lighting(
+            0,
+            LayerLighting {
+                ambient: [0.2, 0.2, 0.3],
+                mask: SKY_LIGHT,
+                ..Default::default()
+            },
+        );
+        // The objects in layer 1 cast shadows onto the ground and the layers above
+        renderer.set_layer_lighting(
+            1,
+            LayerLighting {
+                occluder: true,
+                ..Default::default()
+            },
+        );
+        // The gameplay layers stand out from the ground and each other
+        renderer.set_layer_occlusion(1, 0, 0.6);
+        renderer.set_layer_occlusion(2, 1, 0.4);
+        renderer.set_layer_occlusion(2, 0, 0.4);
+        // The HUD is drawn as is
+        renderer.set_layer_lighting(
+            3,
+            LayerLighting {
+                mask: 0,
+                ..Default::default()
+            },
+        );
+
         self.renderer = Some(renderer);
 
         window.request_redraw();
@@ -56,6 +199,7 @@ impl ApplicationHandler for App {
         }
     }
 
+    #[allow(clippy::collapsible_if, clippy::collapsible_match)]
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
@@ -100,42 +244,215 @@ impl ApplicationHandler for App {
                             self.camera.size[0] *= 0.95;
                             self.camera.size[1] *= 0.95;
                         }
+                        Key::Character("h") => {
+                            self.show_marker = !self.show_marker;
+                        }
+                        Key::Character("p") => {
+                            if let Some(renderer) = &mut self.renderer {
+                                self.fountain = match self.fountain.take() {
+                                    Some(fountain) => {
+                                        renderer.remove_emitter(fountain);
+                                        None
+                                    }
+                                    None => Some(renderer.create_emitter(
+                                        2,
+                                        4096,
+                                        &fountain_emitter(self.start.elapsed().as_secs_f32()),
+                                    )),
+                                };
+                            }
+                        }
                         _ => (),
                     }
                 }
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = [position.x as u32, position.y as u32];
+            }
+            // Click to pick the object under the cursor, drag to select every object in a box
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => match state {
+                ElementState::Pressed => self.drag_start = Some(self.cursor),
+                ElementState::Released => {
+                    if let (Some(renderer), Some(start)) = (&self.renderer, self.drag_start.take())
+                    {
+                        let min = [start[0].min(self.cursor[0]), start[1].min(self.cursor[1])];
+                        let max = [start[0].max(self.cursor[0]), start[1].max(self.cursor[1])];
+                        self.selecting = Some(if max[0] - min[0] < 4 && max[1] - min[1] < 4 {
+                            let picked = renderer.pick(self.cursor[0], self.cursor[1]);
+                            Box::pin(async move { picked.await.into_iter().collect() })
+                        } else {
+                            Box::pin(renderer.pick_rect(min, max))
+                        });
+                    }
+                }
+            },
             WindowEvent::RedrawRequested => {
-                let objects = vec![
+                let mut objects = vec![
                     vec![Object {
                         pos: [100.0, 100.0],
                         size: [250.0, 25.0],
                         color: [0.0, 1.0, 0.0, 1.0],
+                        ..Default::default()
                     }],
                     vec![
                         Object {
                             pos: [200.0, 200.0],
                             size: [200.0, 200.0],
                             color: [1.0, 0.0, 0.0, 1.0],
+                            material: self.stripes,
+                            ..Default::default()
                         },
                         Object {
                             pos: [500.0, 400.0],
                             size: [300.0, 500.0],
                             color: [0.0, 0.0, 1.0, 1.0],
+                            normal_map: self.bumps,
+                            ..Default::default()
                         },
                     ],
                     vec![Object {
                         pos: [750.0, 600.0],
                         size: [500.0, 50.0],
                         color: [1.0, 1.0, 1.0, 1.0],
+                        material: self.checker,
+                        flags: OBJECT_FLIP_X | OBJECT_FLIP_Y,
+                        ..Default::default()
                     }],
-                    vec![Object {
-                        pos: [700.0, 650.0],
-                        size: [20.0, 50.0],
-                        color: [1.0, 0.0, 1.0, 1.0],
-                    }],
+                    vec![
+                        Object {
+                            pos: [700.0, 650.0],
+                            size: [20.0, 50.0],
+                            color: [1.0, 0.0, 1.0, 1.0],
+                            flags: if self.show_marker { 0 } else { OBJECT_HIDDEN },
+                            ..Default::default()
+                        },
+                        // HUD bar pinned to the top left corner of the window
+                        Object {
+                            pos: [16.0, 16.0],
+                            size: [160.0, 12.0],
+                            color: [0.9, 0.2, 0.2, 1.0],
+                            emissive: [1.0, 0.3, 0.3],
+                            emissive_intensity: 0.5,
+                            flags: OBJECT_SCREEN_SPACE,
+                            ..Default::default()
+                        },
+                    ],
                 ];
+                // The readback finishes during a later frame, checked once per frame
+                if let Some(selecting) = &mut self.selecting {
+                    let mut context = Context::from_waker(Waker::noop());
+                    if let Poll::Ready(selected) = selecting.as_mut().poll(&mut context) {
+                        self.selected = selected;
+                        self.selecting = None;
+                    }
+                }
+                // Selected objects glow
+                for &(layer, index) in &self.selected {
+                    if let Some(object) = objects
+                        .get_mut(layer as usize)
+                        .and_then(|layer| layer.get_mut(index as usize))
+                    {
+                        object.emissive = [1.0, 0.8, 0.2];
+                        object.emissive_intensity = 0.6;
+                    }
+                }
 
                 if let Some(renderer) = &mut self.renderer {
+                    let strength = self.start.elapsed().as_secs_f32().sin() * 0.5 + 0.5;
+                    renderer.update_material(
+                        self.stripes,
+                        bytemuck::cast_slice(&[8.0f32, strength, 0.0, 0.0]),
+                    );
+                    if let Some(mesh) = self.terrain {
+                        let (vertices, indices) = terrain(self.start.elapsed().as_secs_f32());
+                        renderer.update_mesh(mesh, &vertices, &indices);
+                        renderer.draw_mesh(
+                            0,
+                            MeshDraw {
+                                mesh,
+                                transform: Transform {
+                                    translation: [0.0, 450.0],
+                                    ..Default::default()
+                                },
+                                texture: None,
+                            },
+                        );
+                    }
+                    if let Some(fountain) = self.fountain {
+                        renderer.update_emitter(
+                            fountain,
+                            &fountain_emitter(self.start.elapsed().as_secs_f32()),
+                        );
+                    }
+                    if let Some(ground) = self.ground {
+                        renderer.draw_tilemap(0, ground);
+                    }
+
+                    // A wide, high sky light over the whole scene
+                    renderer.draw_light(Light {
+                        pos: [400.0, -200.0],
+                        height: 400.0,
+                        radius: 1200.0,
+                        color: [0.8, 0.85, 1.0],
+                        intensity: 0.6,
+                        falloff: 0.5,
+                        mask: SKY_LIGHT,
+                        ..Default::default()
+                    });
+                    // A soft lamp circling the bumpy object and a glow following the fountain
+                    let time = self.start.elapsed().as_secs_f32();
+                    renderer.draw_light(Light {
+                        pos: [650.0 + time.cos() * 200.0, 650.0 + time.sin() * 200.0],
+                        height: 40.0,
+                        radius: 350.0,
+                        color: [1.0, 0.9, 0.7],
+                        intensity: 1.5,
+                        mask: DYNAMIC_LIGHTS,
+                        size: 12.0,
+                        ..Default::default()
+                    });
+                    // A flashlight sweeping across the gameplay layers
+                    let sweep = time.sin() * 0.6 + std::f32::consts::FRAC_PI_2;
+                    renderer.draw_light(Light {
+                        pos: [150.0, 80.0],
+                        radius: 600.0,
+                        color: [0.7, 0.85, 1.0],
+                        intensity: 2.0,
+                        falloff: 1.0,
+                        direction: [sweep.cos(), sweep.sin()],
+                        inner_angle: 0.2,
+                        outer_angle: 0.35,
+                        mask: DYNAMIC_LIGHTS,
+                        ..Default::default()
+                    });
+                    if self.fountain.is_some() {
+                        renderer.draw_light(Light {
+                            pos: fountain_emitter(time).pos,
+                            radius: 250.0,
+                            color: [1.0, 0.5, 0.2],
+                            mask: DYNAMIC_LIGHTS,
+                            gradient: self.flicker,
+                            gradient_period: 0.4,
+                            ..Default::default()
+                        });
+                    }
+                    // Moonlight through a window onto the ground
+                    renderer.draw_light(Light {
+                        pos: [620.0, 180.0],
+                        height: 300.0,
+                        radius: 120.0,
+                        color: [0.6, 0.7, 1.0],
+                        falloff: 0.5,
+                        mask: SKY_LIGHT,
+                        cookie: self.window_cookie,
+                        cookie_rotation: 0.3,
+                        cookie_scale: [0.8, 1.0],
+                        ..Default::default()
+                    });
                     renderer.render(objects, &self.camera);
                 }
             }
@@ -144,6 +461,110 @@ impl ApplicationHandler for App {
     }
 }
 
+// Sparks sprayed upwards from a point sweeping left and right
+fn fountain_emitter(time: f32) -> EmitterDescriptor {
+    EmitterDescriptor {
+        pos: [400.0 + time.sin() * 200.0, 500.0],
+        spawn_rate: 400.0,
+        lifetime: 2.0,
+        velocity: [0.0, -300.0],
+        velocity_spread: [80.0, 60.0],
+        gravity: [0.0, 250.0],
+        color_start: [1.0, 0.8, 0.2, 1.0],
+        color_end: [1.0, 0.1, 0.0, 0.0],
+        size_start: 8.0,
+        size_end: 2.0,
+        ..Default::default()
+    }
+}
+
+const BUMPS_SIZE: u32 = 16;
+
+// Tangent-space normal map of a single dome, tiled over the whole object
+fn bumps() -> Vec<u8> {
+    (0..BUMPS_SIZE * BUMPS_SIZE)
+        .flat_map(|i| {
+            let x = ((i % BUMPS_SIZE) as f32 + 0.5) / BUMPS_SIZE as f32 * 2.0 - 1.0;
+            // Normal maps point green up, rows go down
+            let y = 1.0 - ((i / BUMPS_SIZE) as f32 + 0.5) / BUMPS_SIZE as f32 * 2.0;
+            let (nx, ny) = if x * x + y * y < 1.0 {
+                (x, y)
+            } else {
+                (0.0, 0.0)
+            };
+            let nz = (1.0 - nx * nx - ny * ny).sqrt();
+            [nx, ny, nz, 1.0].map(|c| ((c * 0.5 + 0.5) * 255.0) as u8)
+        })
+        .collect()
+}
+
+const WINDOW_SIZE: u32 = 32;
+
+// Cookie of a window with four panes, the frame blocks the light
+fn window_frame() -> Vec<u8> {
+    (0..WINDOW_SIZE * WINDOW_SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % WINDOW_SIZE, i / WINDOW_SIZE);
+            let frame =
+                |v: u32| !(2..WINDOW_SIZE - 2).contains(&v) || v.abs_diff(WINDOW_SIZE / 2) < 2;
+            if frame(x) || frame(y) {
+                [0, 0, 0, 255]
+            } else {
+                [255, 255, 255, 255]
+            }
+        })
+        .collect()
+}
+
+const TILESET_COLUMNS: u32 = 4;
+
+// 8x8 tiles: grass, a corner marker and two water frames
+fn tileset() -> Vec<u8> {
+    let width = TILESET_COLUMNS * 8;
+    (0..width * 8)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            let (tile, lx, ly) = (x / 8, x % 8, y % 8);
+            match tile {
+                0 => [40, 90, 40, 255],
+                1 if lx < 3 && ly < 3 => [200, 180, 60, 255],
+                1 => [40, 90, 40, 255],
+                2 => [30, 60, 160 + (ly * 8) as u8, 255],
+                _ => [30, 60, 220 - (ly * 8) as u8, 255],
+            }
+        })
+        .collect()
+}
+
+// A strip of hills whose surface waves over time
+fn terrain(time: f32) -> (Vec<Vertex>, Vec<u32>) {
+    const COLUMNS: u32 = 32;
+    const WIDTH: f32 = 800.0;
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for i in 0..=COLUMNS {
+        let t = i as f32 / COLUMNS as f32;
+        let height = 60.0 + (t * 12.0 + time).sin() * 25.0;
+        vertices.push(Vertex {
+            pos: [t * WIDTH, 150.0 - height],
+            color: [0.4, 0.8, 0.3, 1.0],
+            uv: [t, 0.0],
+        });
+        vertices.push(Vertex {
+            pos: [t * WIDTH, 150.0],
+            color: [0.3, 0.2, 0.1, 1.0],
+            uv: [t, 1.0],
+        });
+        if i < COLUMNS {
+            let top = i * 2;
+            indices.extend_from_slice(&[top, top + 1, top + 2, top + 2, top + 1, top + 3]);
+        }
+    }
+
+    (vertices, indices)
+}
+
 fn main() {
     let mut app = App::default();
     let event_loop = EventLoop::new().unwrap();
@@ -0,0 +1,23 @@
+// Color management
+//
+// Colors handed to the renderer are authored in sRGB, like colors picked in an image
+// editor, and converted to linear before they reach the GPU. Lighting and blending
+// happen in linear space, the composite encodes back to sRGB for the surface.
+
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Alpha is coverage and stays as it is
+pub(crate) fn srgba_to_linear(c: [f32; 4]) -> [f32; 4] {
+    [
+        srgb_to_linear(c[0]),
+        srgb_to_linear(c[1]),
+        srgb_to_linear(c[2]),
+        c[3],
+    ]
+}
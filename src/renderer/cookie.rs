@@ -0,0 +1,140 @@
+use wgpu::{
+    Device, Extent3d, Origin3d, Queue, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+};
+
+// Width and height every cookie is resampled to
+pub(crate) const COOKIE_SIZE: u32 = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CookieId(pub(crate) u32);
+
+// Images masking the shape of lights, see `Light::cookie`. They share one sRGB texture
+// array so the lighting pass can pick any of them per light, each resampled to
+// `COOKIE_SIZE` when created. The pixels are kept to fill a larger array when it grows.
+pub struct Cookies {
+    pixels: Vec<Vec<u8>>,
+    view: TextureView,
+    capacity: u32,
+}
+
+impl Cookies {
+    pub fn new(device: &Device) -> Self {
+        // GL makes textures of a single layer plain 2D ones, which arrays cannot sample
+        let capacity = 2;
+        Self {
+            pixels: Vec::new(),
+            view: Self::create_array(device, capacity),
+            capacity,
+        }
+    }
+
+    fn create_array(device: &Device, capacity: u32) -> TextureView {
+        device
+            .create_texture(&TextureDescriptor {
+                label: Some("Cookies"),
+                size: Extent3d {
+                    width: COOKIE_SIZE,
+                    height: COOKIE_SIZE,
+                    depth_or_array_layers: capacity,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    }
+
+    pub fn create(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> CookieId {
+        assert!(width > 0 && height > 0, "cookies cannot be empty");
+        assert_eq!(
+            rgba.len(),
+            width as usize * height as usize * 4,
+            "cookie data must be tightly packed RGBA8"
+        );
+
+        let id = CookieId(self.pixels.len() as u32);
+        self.pixels.push(resample(width, height, rgba));
+        if self.pixels.len() as u32 > self.capacity {
+            self.capacity = (self.pixels.len() as u32).next_power_of_two();
+            self.view = Self::create_array(device, self.capacity);
+            for layer in 0..self.pixels.len() as u32 {
+                self.write(queue, layer);
+            }
+        } else {
+            self.write(queue, id.0);
+        }
+        id
+    }
+
+    fn write(&self, queue: &Queue, layer: u32) {
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: self.view.texture(),
+                mip_level: 0,
+                origin: Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: TextureAspect::All,
+            },
+            &self.pixels[layer as usize],
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(COOKIE_SIZE * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: COOKIE_SIZE,
+                height: COOKIE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+}
+
+// Bilinear resampling of RGBA8 pixels to `COOKIE_SIZE` on both sides, from at least 1x1
+fn resample(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    if width == COOKIE_SIZE && height == COOKIE_SIZE {
+        return rgba.to_vec();
+    }
+    let texel = |x: u32, y: u32, channel: usize| {
+        rgba[(y as usize * width as usize + x as usize) * 4 + channel]
+    };
+    let axis = |dst: u32, src: u32| {
+        let f = ((dst as f32 + 0.5) * src as f32 / COOKIE_SIZE as f32 - 0.5).max(0.0);
+        let low = (f as u32).min(src - 1);
+        (low, (low + 1).min(src - 1), f.fract())
+    };
+
+    let mut pixels = Vec::with_capacity((COOKIE_SIZE * COOKIE_SIZE * 4) as usize);
+    for y in 0..COOKIE_SIZE {
+        let (y0, y1, fy) = axis(y, height);
+        for x in 0..COOKIE_SIZE {
+            let (x0, x1, fx) = axis(x, width);
+            for channel in 0..4 {
+                let top =
+                    texel(x0, y0, channel) as f32 * (1.0 - fx) + texel(x1, y0, channel) as f32 * fx;
+                let bottom =
+                    texel(x0, y1, channel) as f32 * (1.0 - fx) + texel(x1, y1, channel) as f32 * fx;
+                pixels.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+            }
+        }
+    }
+    pixels
+}
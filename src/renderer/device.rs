@@ -1,14 +1,20 @@
 use std::sync::Arc;
 
 use wgpu::{
-    CompositeAlphaMode, Device, Instance, PresentMode, Queue, RequestAdapterOptions, Surface,
-    SurfaceConfiguration, TextureUsages,
+    Adapter, CompositeAlphaMode, Device, DeviceDescriptor, Features, Instance, PresentMode, Queue,
+    RequestAdapterOptions, Surface, SurfaceConfiguration, TextureUsages,
 };
 use winit::window::Window;
 
 pub async fn init_wgpu(
     window: Arc<Window>,
-) -> (Device, Queue, Surface<'static>, SurfaceConfiguration) {
+) -> (
+    Adapter,
+    Device,
+    Queue,
+    Surface<'static>,
+    SurfaceConfiguration,
+) {
     let size = window.inner_size();
 
     let instance = Instance::default();
@@ -22,11 +28,27 @@ pub async fn init_wgpu(
         .await
         .unwrap();
 
-    let (device, queue) = adapter.request_device(&Default::default()).await.unwrap();
+    // Lets MSAA use every sample count the adapter supports, not just the portable ones
+    let (device, queue) = adapter
+        .request_device(&DeviceDescriptor {
+            required_features: adapter.features()
+                & Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // Prefer an sRGB surface, otherwise the composite encodes to sRGB itself
+    let formats = surface.get_capabilities(&adapter).formats;
+    let format = formats
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .unwrap_or(formats[0]);
 
     let config = SurfaceConfiguration {
         usage: TextureUsages::RENDER_ATTACHMENT,
-        format: surface.get_capabilities(&adapter).formats[0],
+        format,
         width: size.width,
         height: size.height,
         present_mode: PresentMode::Fifo,
@@ -36,5 +58,5 @@ pub async fn init_wgpu(
     };
     surface.configure(&device, &config);
 
-    (device, queue, surface, config)
+    (adapter, device, queue, surface, config)
 }
@@ -1,6 +1,9 @@
 use wgpu::{
-    Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    TextureView, TextureViewDescriptor, TextureViewDimension,
+    Adapter, Backend, BlendState, Color, ColorTargetState, ColorWrites, CommandEncoder, Device,
+    Extent3d, Features, LoadOp, MultisampleState, Operations, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension,
 };
 
 #[repr(C)]
@@ -9,45 +12,377 @@ pub struct Frame {
     pub size: [u32; 2],
 }
 
+// Size the G-buffer is rendered at, the composite scales it to the surface
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum Resolution {
+    // Same as the surface
+    Native,
+    // Virtual resolution kept whatever the surface size, such as 320x180 for pixel art
+    Fixed(u32, u32),
+    // Surface size multiplied by the factor
+    Scale(f32),
+}
+
+impl Resolution {
+    pub fn size(self, width: u32, height: u32) -> (u32, u32) {
+        let (width, height) = match self {
+            Resolution::Native => (width, height),
+            Resolution::Fixed(width, height) => (width, height),
+            Resolution::Scale(scale) => (
+                (width as f32 * scale) as u32,
+                (height as f32 * scale) as u32,
+            ),
+        };
+        (width.max(1), height.max(1))
+    }
+}
+
+const NORMAL_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+const EMISSIVE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+const LIGHT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth24Plus;
+// Storable and filterable, f16 keeps whole pixel distances exact up to 2048
+const SDF_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+// Only the id texture is kept around for picking, views keep the others alive
 pub struct GBuffer {
-    pub color_texture: Texture,
     pub color_view: TextureView,
-    pub depth_texture: Texture,
+    pub normal_view: TextureView,
+    pub emissive_view: TextureView,
+    pub id_texture: Texture,
+    pub depth_view: TextureView,
+    pub light_view: TextureView,
+    // Signed distance in pixels to the nearest occluder in r, negative inside occluders.
+    // Written by the SDF pass, sampled by lighting and materials.
+    pub sdf_view: TextureView,
     pub format: TextureFormat,
+    pub normal_format: TextureFormat,
+    pub emissive_format: TextureFormat,
+    pub id_format: TextureFormat,
     pub depth_format: TextureFormat,
+    pub light_format: TextureFormat,
+    pub sdf_format: TextureFormat,
     pub layers: u32,
     pub size: (u32, u32),
+    pub sample_count: u32,
+    pub msaa: Option<MsaaViews>,
+    layer_views: Vec<LayerViews>,
+}
+
+// Multisampled attachments shared by all layers, which are drawn one after another.
+// The resolve pass copies them into the layer once all of its passes are done.
+#[derive(Clone)]
+pub struct MsaaViews {
+    pub color: TextureView,
+    pub normal: TextureView,
+    pub emissive: TextureView,
+    pub id: TextureView,
+    pub depth: TextureView,
+}
+
+// Views of a single layer, attached by every pass drawing into it. Created once with the
+// G-buffer as passes ask for them for every layer every frame.
+pub struct LayerViews {
+    pub color: TextureView,
+    pub normal: TextureView,
+    pub emissive: TextureView,
+    pub id: TextureView,
+    pub depth: TextureView,
+    // Written by the lighting pass once the layer is done, not a geometry target
+    pub light: TextureView,
+    msaa: Option<MsaaViews>,
+    clear_label: String,
+}
+
+impl LayerViews {
+    pub fn color_attachments(&self) -> [Option<RenderPassColorAttachment<'_>>; 4] {
+        self.attachments(Operations {
+            load: LoadOp::Load,
+            store: StoreOp::Store,
+        })
+    }
+
+    fn attachments(&self, ops: Operations<Color>) -> [Option<RenderPassColorAttachment<'_>>; 4] {
+        let views = match &self.msaa {
+            Some(msaa) => [&msaa.color, &msaa.normal, &msaa.emissive, &msaa.id],
+            None => [&self.color, &self.normal, &self.emissive, &self.id],
+        };
+        views.map(|view| {
+            Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops,
+                depth_slice: None,
+            })
+        })
+    }
+
+    pub fn depth_attachment(&self) -> RenderPassDepthStencilAttachment<'_> {
+        self.depth_attachment_with(Operations {
+            load: LoadOp::Load,
+            store: StoreOp::Store,
+        })
+    }
+
+    fn depth_attachment_with(&self, ops: Operations<f32>) -> RenderPassDepthStencilAttachment<'_> {
+        RenderPassDepthStencilAttachment {
+            view: self.msaa.as_ref().map_or(&self.depth, |msaa| &msaa.depth),
+            depth_ops: Some(ops),
+            stencil_ops: None,
+        }
+    }
 }
 
 impl GBuffer {
-    pub fn new(device: &Device, width: u32, height: u32, layers: u32) -> Self {
-        let format = TextureFormat::Rgba8Unorm;
-        let depth_format = TextureFormat::Depth24Plus;
+    // `format` is the color format, `Rgba8UnormSrgb` or `Rgba16Float` for HDR. Both hold
+    // linear colors, the sRGB one only stores them with more precision in the darks.
+    // `sample_count` must be supported, see `supported_sample_count`.
+    pub fn new(
+        device: &Device,
+        width: u32,
+        height: u32,
+        layers: u32,
+        format: TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        assert!(
+            matches!(
+                format,
+                TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba16Float
+            ),
+            "unsupported G-buffer format {:?}",
+            format
+        );
+        let normal_format = NORMAL_FORMAT;
+        let emissive_format = EMISSIVE_FORMAT;
+        let id_format = ID_FORMAT;
+        let depth_format = DEPTH_FORMAT;
+        let light_format = LIGHT_FORMAT;
+
+        let color_texture =
+            Self::create_array(device, "GBuffer Color Array", format, width, height, layers);
+        let color_view = color_texture.create_view(&TextureViewDescriptor {
+            label: Some("GBuffer Color View"),
+            dimension: Some(TextureViewDimension::D2Array),
+            base_array_layer: 0,
+            array_layer_count: Some(layers),
+            ..Default::default()
+        });
+
+        // Normals are stored as `n * 0.5 + 0.5` in rgb with the coverage in alpha
+        let normal_texture = Self::create_array(
+            device,
+            "GBuffer Normal Array",
+            normal_format,
+            width,
+            height,
+            layers,
+        );
+        let normal_view = normal_texture.create_view(&TextureViewDescriptor {
+            label: Some("GBuffer Normal View"),
+            dimension: Some(TextureViewDimension::D2Array),
+            base_array_layer: 0,
+            array_layer_count: Some(layers),
+            ..Default::default()
+        });
+
+        // Emitted light is added after lighting, so it is kept unbounded
+        let emissive_texture = Self::create_array(
+            device,
+            "GBuffer Emissive Array",
+            emissive_format,
+            width,
+            height,
+            layers,
+        );
+        let emissive_view = emissive_texture.create_view(&TextureViewDescriptor {
+            label: Some("GBuffer Emissive View"),
+            dimension: Some(TextureViewDimension::D2Array),
+            base_array_layer: 0,
+            array_layer_count: Some(layers),
+            ..Default::default()
+        });
+
+        // Only read back for picking, never sampled
+        let id_texture =
+            Self::create_array(device, "GBuffer Id Array", id_format, width, height, layers);
+
+        let depth_texture = Self::create_array(
+            device,
+            "GBuffer Depth Array",
+            depth_format,
+            width,
+            height,
+            layers,
+        );
+        let depth_view = depth_texture.create_view(&TextureViewDescriptor {
+            label: Some("GBuffer Depth View"),
+            dimension: Some(TextureViewDimension::D2Array),
+            aspect: TextureAspect::DepthOnly,
+            base_array_layer: 0,
+            array_layer_count: Some(layers),
+            ..Default::default()
+        });
+
+        // Light reaching each layer, multiplied with its color by the composite
+        let light_texture = Self::create_array(
+            device,
+            "GBuffer Light Array",
+            light_format,
+            width,
+            height,
+            layers,
+        );
+        let light_view = light_texture.create_view(&TextureViewDescriptor {
+            label: Some("GBuffer Light View"),
+            dimension: Some(TextureViewDimension::D2Array),
+            base_array_layer: 0,
+            array_layer_count: Some(layers),
+            ..Default::default()
+        });
+
+        let sdf_format = SDF_FORMAT;
+        let sdf_view = device
+            .create_texture(&TextureDescriptor {
+                label: Some("GBuffer SDF"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: sdf_format,
+                usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default());
 
-        let color_texture = device.create_texture(&TextureDescriptor {
-            label: Some("GBuffer Color Array"),
+        let msaa = (sample_count > 1).then(|| {
+            let view = |name: &str, format| {
+                Self::create_msaa(device, name, format, width, height, sample_count)
+                    .create_view(&Default::default())
+            };
+            MsaaViews {
+                color: view("Color", format),
+                normal: view("Normal", normal_format),
+                emissive: view("Emissive", emissive_format),
+                id: view("Id", id_format),
+                depth: view("Depth", depth_format),
+            }
+        });
+
+        let layer_views = (0..layers)
+            .map(|layer| LayerViews {
+                color: Self::layer_view(&color_texture, "Color", layer),
+                normal: Self::layer_view(&normal_texture, "Normal", layer),
+                emissive: Self::layer_view(&emissive_texture, "Emissive", layer),
+                id: Self::layer_view(&id_texture, "Id", layer),
+                depth: Self::layer_view(&depth_texture, "Depth", layer),
+                light: Self::layer_view(&light_texture, "Light", layer),
+                msaa: msaa.clone(),
+                clear_label: format!("GBuffer Clear Layer {}", layer),
+            })
+            .collect();
+
+        Self {
+            color_view,
+            normal_view,
+            emissive_view,
+            id_texture,
+            depth_view,
+            light_view,
+            sdf_view,
+            format,
+            normal_format,
+            emissive_format,
+            id_format,
+            depth_format,
+            light_format,
+            sdf_format,
+            size: (width, height),
+            layers,
+            sample_count,
+            msaa,
+            layer_views,
+        }
+    }
+
+    // Largest count up to `requested` every target can be multisampled with
+    pub fn supported_sample_count(
+        adapter: &Adapter,
+        device: &Device,
+        format: TextureFormat,
+        requested: u32,
+    ) -> u32 {
+        // The GL backend cannot bind multisampled textures, which the resolve pass samples
+        if adapter.get_info().backend == Backend::Gl {
+            return 1;
+        }
+
+        let features = device.features();
+        let format_flags = |format: TextureFormat| {
+            if features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+                adapter.get_texture_format_features(format).flags
+            } else {
+                format.guaranteed_format_features(features).flags
+            }
+        };
+
+        [8, 4, 2]
+            .into_iter()
+            .filter(|count| *count <= requested)
+            .find(|count| {
+                [
+                    format,
+                    NORMAL_FORMAT,
+                    EMISSIVE_FORMAT,
+                    ID_FORMAT,
+                    DEPTH_FORMAT,
+                ]
+                .iter()
+                .all(|format| format_flags(*format).sample_count_supported(*count))
+            })
+            .unwrap_or(1)
+    }
+
+    fn create_msaa(
+        device: &Device,
+        name: &str,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some(&format!("GBuffer MSAA {}", name)),
             size: Extent3d {
                 width,
                 height,
-                depth_or_array_layers: layers,
+                depth_or_array_layers: 1,
             },
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: TextureDimension::D2,
             format,
             usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
-        });
-        let color_view = color_texture.create_view(&TextureViewDescriptor {
-            label: Some("GBuffer Color View"),
-            dimension: Some(TextureViewDimension::D2Array),
-            base_array_layer: 0,
-            array_layer_count: Some(layers),
-            ..Default::default()
-        });
+        })
+    }
 
-        let depth_texture = device.create_texture(&TextureDescriptor {
-            label: Some("GBuffer Depth Array"),
+    fn create_array(
+        device: &Device,
+        label: &str,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        layers: u32,
+    ) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some(label),
             size: Extent3d {
                 width,
                 height,
@@ -56,19 +391,23 @@ impl GBuffer {
             mip_level_count: 1,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format: depth_format,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
             view_formats: &[],
-        });
+        })
+    }
 
-        Self {
-            color_texture,
-            color_view,
-            depth_texture,
-            format,
-            depth_format,
-            size: (width, height),
-            layers,
+    // HDR colors can exceed 1.0 and are tonemapped in the composite
+    pub fn is_hdr(&self) -> bool {
+        self.format == TextureFormat::Rgba16Float
+    }
+
+    pub fn multisample(&self) -> MultisampleState {
+        MultisampleState {
+            count: self.sample_count,
+            ..Default::default()
         }
     }
 
@@ -78,9 +417,40 @@ impl GBuffer {
         }
     }
 
-    pub fn color_layer_view(&self, layer: u32) -> TextureView {
-        self.color_texture.create_view(&TextureViewDescriptor {
-            label: Some(&format!("GBuffer Color Layer {}", layer)),
+    // Matches the order of `LayerViews::color_attachments`, only object ids are
+    // written to the id target when `write_ids` is set
+    pub fn color_targets(
+        &self,
+        blend: BlendState,
+        write_ids: bool,
+    ) -> [Option<ColorTargetState>; 4] {
+        let [color, normal, emissive] = [self.format, self.normal_format, self.emissive_format]
+            .map(|format| {
+                Some(ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: ColorWrites::ALL,
+                })
+            });
+        let id = Some(ColorTargetState {
+            format: self.id_format,
+            blend: None,
+            write_mask: if write_ids {
+                ColorWrites::ALL
+            } else {
+                ColorWrites::empty()
+            },
+        });
+        [color, normal, emissive, id]
+    }
+
+    pub fn layer_views(&self, layer: u32) -> &LayerViews {
+        &self.layer_views[layer as usize]
+    }
+
+    fn layer_view(texture: &Texture, name: &str, layer: u32) -> TextureView {
+        texture.create_view(&TextureViewDescriptor {
+            label: Some(&format!("GBuffer {} Layer {}", name, layer)),
             dimension: Some(TextureViewDimension::D2),
             base_array_layer: layer,
             array_layer_count: Some(1),
@@ -88,13 +458,21 @@ impl GBuffer {
         })
     }
 
-    pub fn depth_layer_view(&self, layer: u32) -> TextureView {
-        self.depth_texture.create_view(&TextureViewDescriptor {
-            label: Some(&format!("GBuffer Depth Layer {}", layer)),
-            dimension: Some(TextureViewDimension::D2),
-            base_array_layer: layer,
-            array_layer_count: Some(1),
+    // Every pass drawing into a layer loads it, so each layer starts here. With MSAA only
+    // the shared attachments are cleared, the resolve pass overwrites the whole layer.
+    pub fn clear_layer(&self, encoder: &mut CommandEncoder, layer: u32) {
+        let views = self.layer_views(layer);
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&views.clear_label),
+            color_attachments: &views.attachments(Operations {
+                load: LoadOp::Clear(Color::TRANSPARENT),
+                store: StoreOp::Store,
+            }),
+            depth_stencil_attachment: Some(views.depth_attachment_with(Operations {
+                load: LoadOp::Clear(1.0),
+                store: StoreOp::Store,
+            })),
             ..Default::default()
-        })
+        });
     }
 }
@@ -0,0 +1,243 @@
+// 2D lights accumulated per layer by the lighting pass
+//
+// Every light is a point light, narrowing `outer_angle` turns it into a spot light with
+// `radius` as its range. Lights only reach the layers whose `LayerLighting::mask` shares a
+// bit with their own `mask`. A cookie masks the light's shape with an image and a gradient
+// animates its color over time.
+
+use std::f32::consts::PI;
+
+use super::{color::srgb_to_linear, cookie::CookieId};
+
+#[derive(Copy, Clone)]
+pub struct Light {
+    /// Center in world space, like `Object::pos`.
+    pub pos: [f32; 2],
+    /// Distance above the layers. Low lights graze normal maps, high ones light them head on.
+    pub height: f32,
+    /// Distance at which the light has faded out completely.
+    pub radius: f32,
+    /// sRGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Exponent of the fade towards `radius`, 1.0 is linear and higher values fall off faster.
+    pub falloff: f32,
+    /// Where a spot light points in world space, normalized when uploaded.
+    pub direction: [f32; 2],
+    /// Angle from `direction` within which a spot light is at full strength, in radians.
+    pub inner_angle: f32,
+    /// Angle from `direction` past which a spot light gives nothing, in radians. `PI` lights
+    /// every direction.
+    pub outer_angle: f32,
+    /// Bits matched against `LayerLighting::mask` of each layer.
+    pub mask: u32,
+    /// Radius of the light's source. 0 casts hard shadows, larger lights cast soft ones
+    /// whose penumbra widens with the size.
+    pub size: f32,
+    /// Image the light is multiplied with, centered on `pos` and reaching `radius` to each
+    /// side. Lights with a cookie give nothing outside of it.
+    pub cookie: Option<CookieId>,
+    /// Rotation of the cookie in radians, turning its x axis towards the world y axis.
+    pub cookie_rotation: f32,
+    /// Size of the cookie on each of its axes relative to `radius`.
+    pub cookie_scale: [f32; 2],
+    /// Colors `color` is multiplied with over time, see `Renderer::create_gradient`.
+    pub gradient: Option<GradientId>,
+    /// Seconds the gradient takes to run through before starting over.
+    pub gradient_period: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            height: 32.0,
+            radius: 100.0,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            falloff: 2.0,
+            direction: [1.0, 0.0],
+            inner_angle: PI,
+            outer_angle: PI,
+            mask: !0,
+            size: 0.0,
+            cookie: None,
+            cookie_rotation: 0.0,
+            cookie_scale: [1.0, 1.0],
+            gradient: None,
+            gradient_period: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GradientId(pub(crate) u32);
+
+// Color gradients lights cycle through, each a list of positions in 0..1 and sRGB colors
+// sorted by position
+#[derive(Default)]
+pub(crate) struct Gradients {
+    stops: Vec<Vec<(f32, [f32; 3])>>,
+}
+
+impl Gradients {
+    pub fn create(&mut self, stops: &[(f32, [f32; 3])]) -> GradientId {
+        assert!(!stops.is_empty(), "gradients need at least one stop");
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let id = GradientId(self.stops.len() as u32);
+        self.stops.push(stops);
+        id
+    }
+
+    // Color at `position` in 0..1, held at the first and last stop past either end
+    fn sample(&self, id: GradientId, position: f32) -> [f32; 3] {
+        let stops = &self.stops[id.0 as usize];
+        let next = stops.partition_point(|(stop, _)| *stop <= position);
+        match next {
+            0 => stops[0].1,
+            _ if next == stops.len() => stops[next - 1].1,
+            _ => {
+                let (start, from) = stops[next - 1];
+                let (end, to) = stops[next];
+                let t = (position - start) / (end - start);
+                std::array::from_fn(|i| from[i] + (to[i] - from[i]) * t)
+            }
+        }
+    }
+
+    // Multiplies the color of a light with a gradient by where it is `time` seconds in
+    pub fn apply(&self, light: &mut Light, time: f32) {
+        let Some(gradient) = light.gradient else {
+            return;
+        };
+        let position = match light.gradient_period {
+            period if period > 0.0 => (time / period).fract(),
+            _ => 0.0,
+        };
+        let color = self.sample(gradient, position);
+        light.color = std::array::from_fn(|i| light.color[i] * color[i]);
+    }
+}
+
+// How one G-buffer layer is lit
+#[derive(Copy, Clone)]
+pub struct LayerLighting {
+    /// Light every pixel of the layer gets before any lights, sRGB.
+    pub ambient: [f32; 3],
+    pub ambient_intensity: f32,
+    /// Lights sharing a bit with this reach the layer. A layer without bits is unlit and
+    /// composited as drawn.
+    pub mask: u32,
+    /// Opaque pixels of this layer block lights, casting hard shadows onto the other lit
+    /// layers. The occluders themselves are lit unshadowed.
+    pub occluder: bool,
+}
+
+impl Default for LayerLighting {
+    fn default() -> Self {
+        Self {
+            ambient: [0.35, 0.35, 0.4],
+            ambient_intensity: 1.0,
+            mask: !0,
+            occluder: false,
+        }
+    }
+}
+
+impl LayerLighting {
+    pub fn is_lit(&self) -> bool {
+        self.mask != 0
+    }
+
+    // Linear ambient the layer's light target is cleared to
+    pub(crate) fn ambient_linear(&self) -> [f32; 3] {
+        self.ambient
+            .map(|c| srgb_to_linear(c) * self.ambient_intensity)
+    }
+}
+
+// Bit per occluder layer, as the shaders take them
+pub(crate) fn occluder_mask(layers: &[LayerLighting]) -> u32 {
+    layers
+        .iter()
+        .enumerate()
+        .filter(|(_, layer)| layer.occluder)
+        .fold(0, |bits, (index, _)| bits | 1 << index)
+}
+
+// GPU layout of a light, matches `Light` in light.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LightRaw {
+    pos: [f32; 2],
+    radius: f32,
+    height: f32,
+    color: [f32; 3],
+    falloff: f32,
+    direction: [f32; 2],
+    cos_inner: f32,
+    cos_outer: f32,
+    mask: u32,
+    size: f32,
+    // Cookie layer plus one, 0 without a cookie
+    cookie: u32,
+    cookie_rotation: f32,
+    cookie_scale: [f32; 2],
+    // Row in the shadow map plus one, 0 for lights without one
+    shadow_row: u32,
+    _pad: u32,
+}
+
+impl From<&Light> for LightRaw {
+    fn from(light: &Light) -> Self {
+        Self {
+            pos: light.pos,
+            radius: light.radius,
+            height: light.height,
+            color: light.color.map(|c| srgb_to_linear(c) * light.intensity),
+            falloff: light.falloff,
+            direction: normalize(light.direction),
+            cos_inner: light.inner_angle.min(light.outer_angle).cos(),
+            cos_outer: light.outer_angle.min(PI).cos(),
+            mask: light.mask,
+            size: light.size,
+            cookie: light.cookie.map_or(0, |cookie| cookie.0 + 1),
+            cookie_rotation: light.cookie_rotation,
+            cookie_scale: light.cookie_scale,
+            shadow_row: 0,
+            _pad: 0,
+        }
+    }
+}
+
+// Sized lights trace soft shadows through the SDF and need no row in the shadow map
+pub(crate) fn casts_hard_shadow(light: &Light) -> bool {
+    light.size <= 0.0
+}
+
+// The frame's lights, those casting hard shadows numbered by their row in the shadow map
+pub(crate) fn lights_raw(lights: &[Light]) -> Vec<LightRaw> {
+    let mut rows = 0;
+    lights
+        .iter()
+        .map(|light| {
+            let mut raw = LightRaw::from(light);
+            if casts_hard_shadow(light) {
+                rows += 1;
+                raw.shadow_row = rows;
+            }
+            raw
+        })
+        .collect()
+}
+
+fn normalize(v: [f32; 2]) -> [f32; 2] {
+    let length = v[0].hypot(v[1]);
+    if length > 0.0 {
+        [v[0] / length, v[1] / length]
+    } else {
+        [1.0, 0.0]
+    }
+}
@@ -0,0 +1,30 @@
+// User-defined materials for the geometry pass
+//
+// A material is a WGSL snippet defining
+//     fn material(input: VSOut) -> vec4<f32>
+// which is appended to geometry.wgsl and called from its `fs_main`.
+// Bindings owned by the material live in `@group(2)`:
+//     @binding(0) uniform, when `MaterialDescriptor::uniform` is set
+//     @binding(1) filtering sampler, when any texture is set
+//     @binding(2 + i) texture_2d<f32> for `MaterialDescriptor::textures[i]`
+// `VSOut::color` is linear and textures from `Renderer::create_texture` are decoded to
+// linear when sampled, the returned color is linear too.
+// The normal target is written by geometry.wgsl from the object's normal map,
+// which is bound in `@group(3)`.
+// `sdf` from geometry.wgsl holds the distance to the nearest occluder for glows and
+// outlines around them.
+
+use super::texture::TextureId;
+
+pub type MaterialId = u32;
+
+pub const DEFAULT_MATERIAL: MaterialId = 0;
+
+pub(crate) const DEFAULT_MATERIAL_SOURCE: &str = include_str!("../shaders/default_material.wgsl");
+
+pub struct MaterialDescriptor<'a> {
+    pub label: &'a str,
+    pub source: &'a str,
+    pub uniform: Option<&'a [u8]>,
+    pub textures: &'a [TextureId],
+}
@@ -0,0 +1,148 @@
+// Triangle meshes drawn into a layer with a transform
+
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferAddress, BufferUsages, Device, VertexAttribute, VertexBufferLayout,
+    VertexStepMode,
+};
+
+use super::texture::TextureId;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub pos: [f32; 2],
+    /// sRGB with straight alpha.
+    pub color: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    const ATTRIBUTES: [VertexAttribute; 3] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x4,
+        2 => Float32x2,
+    ];
+
+    pub(crate) fn layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Transform {
+    pub translation: [f32; 2],
+    pub scale: [f32; 2],
+    pub rotation: f32,
+    /// Written to the layer's depth target like `Object::depth`.
+    pub depth: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: [0.0, 0.0],
+            scale: [1.0, 1.0],
+            rotation: 0.0,
+            depth: 0.0,
+        }
+    }
+}
+
+// GPU layout of a transform, matches `Transform` in mesh.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TransformRaw {
+    translation: [f32; 2],
+    scale: [f32; 2],
+    rotation: f32,
+    depth: f32,
+    _pad: [f32; 2],
+}
+
+impl From<&Transform> for TransformRaw {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation,
+            scale: transform.scale,
+            rotation: transform.rotation,
+            depth: transform.depth,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MeshId(pub(crate) u32);
+
+#[derive(Copy, Clone)]
+pub struct MeshDraw {
+    pub mesh: MeshId,
+    pub transform: Transform,
+    pub texture: Option<TextureId>,
+}
+
+pub(crate) struct MeshBuffers {
+    pub vertex_b: Buffer,
+    pub index_b: Buffer,
+    pub index_count: u32,
+}
+
+impl MeshBuffers {
+    fn new(device: &Device, id: MeshId, vertices: &[Vertex], indices: &[u32]) -> Self {
+        // Also rejects indices without any vertices, which would otherwise still be drawn
+        assert!(
+            indices
+                .iter()
+                .all(|&index| (index as usize) < vertices.len()),
+            "mesh {} has indices past its {} vertices",
+            id.0,
+            vertices.len()
+        );
+
+        let vertex_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Mesh {} Vertex B", id.0)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: BufferUsages::VERTEX,
+        });
+        let index_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Mesh {} Index B", id.0)),
+            contents: bytemuck::cast_slice(indices),
+            usage: BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_b,
+            index_b,
+            index_count: indices.len() as u32,
+        }
+    }
+}
+
+// Meshes uploaded by the user, referenced from draws by `MeshId`
+#[derive(Default)]
+pub struct Meshes {
+    buffers: Vec<MeshBuffers>,
+}
+
+impl Meshes {
+    pub fn create(&mut self, device: &Device, vertices: &[Vertex], indices: &[u32]) -> MeshId {
+        let id = MeshId(self.buffers.len() as u32);
+        self.buffers
+            .push(MeshBuffers::new(device, id, vertices, indices));
+        id
+    }
+
+    // Buffers are recreated so the vertex and index counts may change
+    pub fn update(&mut self, device: &Device, id: MeshId, vertices: &[Vertex], indices: &[u32]) {
+        self.buffers[id.0 as usize] = MeshBuffers::new(device, id, vertices, indices);
+    }
+
+    pub(crate) fn get(&self, id: MeshId) -> &MeshBuffers {
+        &self.buffers[id.0 as usize]
+    }
+}
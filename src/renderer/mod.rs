@@ -1,20 +1,81 @@
-mod device;
-mod gbuffer;
 pub mod camera;
+mod color;
+pub mod cookie;
+mod device;
+pub mod gbuffer;
+pub mod light;
+pub mod material;
+pub mod mesh;
 pub mod object;
-mod passes;
+pub mod particles;
+pub mod passes;
+mod picking;
+pub mod texture;
+pub mod tilemap;
 
 use camera::Camera;
+use cookie::{CookieId, Cookies};
+use light::{GradientId, Gradients, LayerLighting, Light};
+use material::{MaterialDescriptor, MaterialId};
+use mesh::{MeshDraw, MeshId, Meshes, Vertex};
 use object::Object;
-use std::sync::Arc;
-use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
+use particles::{EmitterDescriptor, EmitterId, Emitters};
+use std::{sync::Arc, time::Instant};
+use wgpu::{Device, PollType, Queue, Surface, SurfaceConfiguration, TextureFormat};
 use winit::window::Window;
 
-use gbuffer::GBuffer;
+use gbuffer::{GBuffer, Resolution};
+use texture::{TextureId, Textures};
+use tilemap::{TilemapDescriptor, TilemapId, Tilemaps};
 
 const LAYERS: u32 = 4;
+const GBUFFER_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+// const GBUFFER_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const RESOLUTION: Resolution = Resolution::Native;
+// const RESOLUTION: Resolution = Resolution::Fixed(320, 180);
+// const RESOLUTION: Resolution = Resolution::Scale(0.5);
+const UPSCALE: passes::Upscale = passes::Upscale::Pixel;
+// const UPSCALE: passes::Upscale = passes::Upscale::Smooth;
+// 1, 2, 4 or 8, lowered to what the adapter supports for the G-buffer formats, always 1 on GL
+const SAMPLE_COUNT: u32 = 4;
 const COMPOSITE_MODE: passes::CompositeMode = passes::CompositeMode::Composite;
 // const COMPOSITE_MODE: passes::CompositeMode = passes::CompositeMode::Grid;
+// const COMPOSITE_MODE: passes::CompositeMode = passes::CompositeMode::Normals;
+// const COMPOSITE_MODE: passes::CompositeMode = passes::CompositeMode::Emissive;
+// const COMPOSITE_MODE: passes::CompositeMode = passes::CompositeMode::Depth;
+// const COMPOSITE_MODE: passes::CompositeMode = passes::CompositeMode::Light;
+// Directions around each light in its shadow map
+const SHADOW_RESOLUTION: u32 = 1024;
+// Bins lights into screen tiles so each pixel only goes through the ones touching it
+const LIGHT_CULLING: bool = true;
+// const LIGHT_CULLING: bool = false;
+// Indirect light bounced off emissive pixels and lit occluders, off by default
+const GLOBAL_ILLUMINATION: Option<passes::GiSettings> = None;
+// const GLOBAL_ILLUMINATION: Option<passes::GiSettings> = Some(passes::GiSettings {
+//     cascades: 5,
+//     scale: 0.5,
+//     bounce: 0.5,
+// });
+// Glow around emissive pixels, or around everything bright enough with an HDR G-buffer
+const BLOOM: Option<passes::BloomSettings> = Some(passes::BloomSettings {
+    source: passes::BloomSource::Emissive,
+    threshold: 0.0,
+    intensity: 0.8,
+    radius: 32.0,
+});
+// const BLOOM: Option<passes::BloomSettings> = Some(passes::BloomSettings {
+//     source: passes::BloomSource::Scene,
+//     threshold: 1.0,
+//     intensity: 0.5,
+//     radius: 64.0,
+// });
+// const BLOOM: Option<passes::BloomSettings> = None;
+// Soft shade layers cast onto the ones beneath them, as strong as `set_layer_occlusion` sets
+const AMBIENT_OCCLUSION: Option<passes::AoSettings> = Some(passes::AoSettings {
+    offset: [3, 4],
+    radius: 6,
+});
+// const AMBIENT_OCCLUSION: Option<passes::AoSettings> = None;
 
 pub struct Renderer {
     pub window: Arc<Window>,
@@ -23,17 +84,62 @@ pub struct Renderer {
     surface: Surface<'static>,
     config: SurfaceConfiguration,
     gbuffer: GBuffer,
+    textures: Textures,
+    meshes: Meshes,
+    mesh_draws: Vec<Vec<MeshDraw>>,
+    tilemaps: Tilemaps,
+    tilemap_draws: Vec<Vec<TilemapId>>,
+    lights: Vec<Light>,
+    cookies: Cookies,
+    gradients: Gradients,
+    layer_lighting: Vec<LayerLighting>,
+    // How strongly each layer shades each one beneath it, at `caster * LAYERS + receiver`
+    layer_occlusion: Vec<f32>,
+    emitters: Emitters,
+    start: Instant,
+    last_render: Instant,
     geometry_pass: passes::Geometry,
+    mesh_pass: passes::Mesh,
+    tilemap_pass: passes::Tilemap,
+    particles_pass: passes::Particles,
+    resolve_pass: passes::Resolve,
+    sdf_pass: passes::Sdf,
+    shadows_pass: passes::Shadows,
+    lighting_pass: passes::Lighting,
+    gi_pass: Option<passes::Gi>,
+    ao_pass: Option<passes::Ao>,
     composite_pass: passes::Composite,
+    bloom_pass: Option<passes::Bloom>,
 }
 
 impl Renderer {
     pub async fn new(window: Arc<Window>) -> Self {
-        let (device, queue, surface, config) = device::init_wgpu(window.clone()).await;
+        let (adapter, device, queue, surface, config) = device::init_wgpu(window.clone()).await;
+        let sample_count =
+            GBuffer::supported_sample_count(&adapter, &device, GBUFFER_FORMAT, SAMPLE_COUNT);
 
-        let gbuffer = GBuffer::new(&device, config.width, config.height, LAYERS);
-        let geometry_pass = passes::Geometry::new(&device, &gbuffer);
-        let composite_pass = passes::Composite::new(&device, config.format, &gbuffer, COMPOSITE_MODE);
+        let (width, height) = RESOLUTION.size(config.width, config.height);
+        let gbuffer = GBuffer::new(&device, width, height, LAYERS, GBUFFER_FORMAT, sample_count);
+        let textures = Textures::default();
+        let cookies = Cookies::new(&device);
+        let geometry_pass = passes::Geometry::new(&device, &queue, &gbuffer, &textures);
+        let mesh_pass = passes::Mesh::new(&device, &queue, &gbuffer);
+        let tilemap_pass = passes::Tilemap::new(&device, &gbuffer);
+        let particles_pass = passes::Particles::new(&device, &gbuffer);
+        let resolve_pass = passes::Resolve::new(&device, &gbuffer);
+        let sdf_pass = passes::Sdf::new(&device, &gbuffer);
+        let shadows_pass = passes::Shadows::new(&device, SHADOW_RESOLUTION);
+        let lighting_pass = passes::Lighting::new(&device, &gbuffer, LIGHT_CULLING);
+        let gi_pass =
+            GLOBAL_ILLUMINATION.map(|settings| passes::Gi::new(&device, &gbuffer, settings));
+        let ao_pass =
+            AMBIENT_OCCLUSION.map(|settings| passes::Ao::new(&device, &gbuffer, settings));
+        let composite_pass =
+            passes::Composite::new(&device, config.format, &gbuffer, COMPOSITE_MODE, UPSCALE);
+        // The grid views are shown as they are
+        let bloom_pass = BLOOM
+            .filter(|_| matches!(COMPOSITE_MODE, passes::CompositeMode::Composite))
+            .map(|settings| passes::Bloom::new(&device, &gbuffer, settings));
 
         Self {
             window,
@@ -42,42 +148,321 @@ impl Renderer {
             surface,
             config,
             gbuffer,
+            textures,
+            meshes: Meshes::default(),
+            mesh_draws: vec![Vec::new(); LAYERS as usize],
+            tilemaps: Tilemaps::default(),
+            tilemap_draws: vec![Vec::new(); LAYERS as usize],
+            lights: Vec::new(),
+            cookies,
+            gradients: Gradients::default(),
+            layer_lighting: vec![LayerLighting::default(); LAYERS as usize],
+            layer_occlusion: vec![0.0; (LAYERS * LAYERS) as usize],
+            emitters: Emitters::default(),
+            start: Instant::now(),
+            last_render: Instant::now(),
             geometry_pass,
+            mesh_pass,
+            tilemap_pass,
+            particles_pass,
+            resolve_pass,
+            sdf_pass,
+            shadows_pass,
+            lighting_pass,
+            gi_pass,
+            ao_pass,
             composite_pass,
+            bloom_pass,
+        }
+    }
+
+    /// Uploads tightly packed sRGB RGBA8 pixels for use by materials.
+    pub fn create_texture(&mut self, width: u32, height: u32, rgba: &[u8]) -> TextureId {
+        self.textures
+            .create(&self.device, &self.queue, width, height, rgba, true)
+    }
+
+    /// Uploads tightly packed RGBA8 values sampled without color conversion, such as normal maps.
+    pub fn create_linear_texture(&mut self, width: u32, height: u32, rgba: &[u8]) -> TextureId {
+        self.textures
+            .create(&self.device, &self.queue, width, height, rgba, false)
+    }
+
+    /// Compiles a geometry pipeline for the material, see `material` for the WGSL contract.
+    pub fn register_material(&mut self, desc: &MaterialDescriptor) -> MaterialId {
+        self.geometry_pass
+            .register_material(&self.device, &self.textures, desc)
+    }
+
+    pub fn update_material(&self, material: MaterialId, uniform: &[u8]) {
+        self.geometry_pass
+            .update_material(&self.queue, material, uniform);
+    }
+
+    pub fn create_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) -> MeshId {
+        self.meshes.create(&self.device, vertices, indices)
+    }
+
+    pub fn update_mesh(&mut self, mesh: MeshId, vertices: &[Vertex], indices: &[u32]) {
+        self.meshes.update(&self.device, mesh, vertices, indices);
+    }
+
+    /// Queues a mesh to be drawn into `layer` on top of its objects during the next `render`.
+    pub fn draw_mesh(&mut self, layer: u32, draw: MeshDraw) {
+        if let Some(draws) = self.mesh_draws.get_mut(layer as usize) {
+            draws.push(draw);
         }
     }
 
+    pub fn create_tilemap(&mut self, desc: &TilemapDescriptor) -> TilemapId {
+        self.tilemaps.create(&self.device, &self.queue, desc)
+    }
+
+    /// Overwrites the `size` region of tiles starting at `origin`, in tiles.
+    pub fn update_tiles(
+        &self,
+        tilemap: TilemapId,
+        origin: [u32; 2],
+        size: [u32; 2],
+        tiles: &[u32],
+    ) {
+        self.tilemaps
+            .update(&self.queue, tilemap, origin, size, tiles);
+    }
+
+    /// Queues a tilemap to be drawn into `layer` beneath its objects during the next `render`.
+    pub fn draw_tilemap(&mut self, layer: u32, tilemap: TilemapId) {
+        if let Some(draws) = self.tilemap_draws.get_mut(layer as usize) {
+            draws.push(tilemap);
+        }
+    }
+
+    /// Queues a light for the next `render`, lighting the layers its mask matches.
+    pub fn draw_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    /// Uploads tightly packed sRGB RGBA8 pixels for lights to use as a cookie, resampled to
+    /// a fixed size. Alpha masks the light along with the color.
+    pub fn create_cookie(&mut self, width: u32, height: u32, rgba: &[u8]) -> CookieId {
+        self.cookies
+            .create(&self.device, &self.queue, width, height, rgba)
+    }
+
+    /// Registers sRGB colors at positions in 0..1 for lights to cycle through, blended
+    /// between neighbouring stops.
+    pub fn create_gradient(&mut self, stops: &[(f32, [f32; 3])]) -> GradientId {
+        self.gradients.create(stops)
+    }
+
+    /// Sets the ambient light of `layer` and which lights reach it, kept until changed.
+    pub fn set_layer_lighting(&mut self, layer: u32, lighting: LayerLighting) {
+        if let Some(layer_lighting) = self.layer_lighting.get_mut(layer as usize) {
+            *layer_lighting = lighting;
+        }
+    }
+
+    /// Sets how much `caster` darkens `receiver` with ambient occlusion, from 0.0 for not at all
+    /// to 1.0 for black under its opaque pixels, kept until changed. Only layers above the
+    /// receiver cast onto it.
+    pub fn set_layer_occlusion(&mut self, caster: u32, receiver: u32, strength: f32) {
+        if caster < LAYERS && receiver < caster {
+            self.layer_occlusion[(caster * LAYERS + receiver) as usize] = strength;
+        }
+    }
+
+    /// Creates an emitter drawing into `layer` every frame, holding at most `capacity` particles.
+    /// The capacity is lowered to what the device can simulate at once.
+    pub fn create_emitter(
+        &mut self,
+        layer: u32,
+        capacity: u32,
+        desc: &EmitterDescriptor,
+    ) -> EmitterId {
+        assert!(layer < LAYERS, "no layer {} to draw an emitter into", layer);
+        self.emitters.create(&self.device, layer, capacity, desc)
+    }
+
+    pub fn update_emitter(&mut self, emitter: EmitterId, desc: &EmitterDescriptor) {
+        self.emitters.update(emitter, desc);
+    }
+
+    pub fn remove_emitter(&mut self, emitter: EmitterId) {
+        self.emitters.remove(emitter);
+    }
+
+    /// Object drawn at window pixel `(x, y)` in the last frame, as its layer and index into the
+    /// objects passed to `render`. The topmost layer wins.
+    ///
+    /// Reads the G-buffer back without blocking. The future does not borrow the renderer and
+    /// resolves once the GPU is done, which later `render` calls check for.
+    pub fn pick(&self, x: u32, y: u32) -> impl Future<Output = Option<(u32, u32)>> + use<> {
+        let (width, height) = self.gbuffer.size;
+        let [x, y] = self.window_to_gbuffer([x as f32 + 0.5, y as f32 + 0.5]);
+        let inside = x >= 0.0 && y >= 0.0 && x < width as f32 && y < height as f32;
+        let ids = inside.then(|| {
+            let origin = [x as u32, y as u32];
+            picking::read_ids(&self.device, &self.queue, &self.gbuffer, origin, [1, 1])
+        });
+
+        async move {
+            let ids = ids?.await;
+            ids.into_iter().rev().find_map(picking::decode_id)
+        }
+    }
+
+    /// Every object with a pixel inside the window rectangle `min..max` in the last frame, on any
+    /// layer, sorted by layer and index. Read back without blocking like `pick`.
+    pub fn pick_rect(
+        &self,
+        min: [u32; 2],
+        max: [u32; 2],
+    ) -> impl Future<Output = Vec<(u32, u32)>> + use<> {
+        let (width, height) = self.gbuffer.size;
+        // Rows are flipped, the window's top edge is the G-buffer's bottom one
+        let [left, top] = self.window_to_gbuffer([min[0] as f32, min[1] as f32]);
+        let [right, bottom] = self.window_to_gbuffer([max[0] as f32, max[1] as f32]);
+        let min = [
+            left.floor().clamp(0.0, width as f32) as u32,
+            bottom.floor().clamp(0.0, height as f32) as u32,
+        ];
+        let max = [
+            right.ceil().clamp(0.0, width as f32) as u32,
+            top.ceil().clamp(0.0, height as f32) as u32,
+        ];
+        let ids = (min[0] < max[0] && min[1] < max[1]).then(|| {
+            let size = [max[0] - min[0], max[1] - min[1]];
+            picking::read_ids(&self.device, &self.queue, &self.gbuffer, min, size)
+        });
+
+        async move {
+            let Some(ids) = ids else {
+                return Vec::new();
+            };
+            let mut ids = ids.await;
+            ids.sort_unstable();
+            ids.dedup();
+            ids.into_iter().filter_map(picking::decode_id).collect()
+        }
+    }
+
+    // G-buffer pixel coordinates shown at a window position, outside of it on the bars
+    fn window_to_gbuffer(&self, pos: [f32; 2]) -> [f32; 2] {
+        let (width, height) = self.gbuffer.size;
+        let [x, y, viewport_width, viewport_height] = self
+            .composite_pass
+            .viewport((self.config.width, self.config.height));
+        // The composite shows the G-buffer with its rows flipped
+        [
+            (pos[0] - x) / viewport_width * width as f32,
+            (1.0 - (pos[1] - y) / viewport_height) * height as f32,
+        ]
+    }
+
     pub fn render(&mut self, objects: Vec<Vec<Object>>, camera: &Camera) {
         let frame = self.surface.get_current_texture().unwrap();
         let surface_view = frame.texture.create_view(&Default::default());
 
         let mut encoder = self.device.create_command_encoder(&Default::default());
 
+        let now = Instant::now();
+        let dt = (now - self.last_render).as_secs_f32();
+        self.last_render = now;
+        self.emitters.advance(&self.queue, dt);
+
+        let time = self.start.elapsed().as_secs_f32();
+        for light in &mut self.lights {
+            self.gradients.apply(light, time);
+        }
+
         let mut rpd = passes::RenderPassData {
             gbuffer: &self.gbuffer,
             encoder: &mut encoder,
             device: &self.device,
             queue: &self.queue,
+            textures: &self.textures,
+            meshes: &self.meshes,
+            tilemaps: &self.tilemaps,
+            emitters: &self.emitters,
+            cookies: &self.cookies,
         };
 
-        for (i, objects) in objects.iter().enumerate()
-        {
-            if i as u32 >= LAYERS {
-                break;
-            }
-            if objects.is_empty() {
-                continue;
-            }
+        self.particles_pass.simulate(&mut rpd);
+        self.lighting_pass
+            .prepare(&mut rpd, &self.lights, &self.layer_lighting, camera);
+        self.mesh_pass.prepare(&mut rpd, &self.mesh_draws, camera);
+        // The SDF is built as soon as the last occluder layer is done, layers above it can
+        // use this frame's
+        let sdf_layer = self
+            .layer_lighting
+            .iter()
+            .rposition(|lighting| lighting.occluder)
+            .map_or(LAYERS - 1, |layer| layer as u32);
+        for layer in 0..LAYERS {
+            self.gbuffer.clear_layer(rpd.encoder, layer);
+            let objects = objects
+                .get(layer as usize)
+                .map_or(&[][..], |o| o.as_slice());
 
-            self.geometry_pass
-                .execute(&mut rpd, objects, i as u32, &camera);
+            self.tilemap_pass.execute(
+                &mut rpd,
+                &self.tilemap_draws[layer as usize],
+                layer,
+                camera,
+                time,
+            );
+            self.geometry_pass.execute(&mut rpd, objects, layer, camera);
+            self.mesh_pass
+                .execute(&mut rpd, &self.mesh_draws[layer as usize], layer);
+            self.particles_pass.execute(&mut rpd, layer, camera);
+            self.resolve_pass.execute(&mut rpd, layer);
+            if layer == sdf_layer {
+                self.sdf_pass.execute(&mut rpd, &self.layer_lighting);
+            }
+        }
+        // Shadows need every occluder layer drawn before any layer is lit
+        self.shadows_pass
+            .execute(&mut rpd, &self.lights, &self.layer_lighting, camera);
+        for layer in 0..LAYERS {
+            self.lighting_pass
+                .execute(&mut rpd, layer, self.shadows_pass.view());
+        }
+        if let Some(gi_pass) = &self.gi_pass {
+            gi_pass.execute(&mut rpd, &self.layer_lighting);
+        }
+        if let Some(ao_pass) = &self.ao_pass {
+            ao_pass.execute(&mut rpd, &self.layer_occlusion);
         }
-        {
-            self.composite_pass.execute(&mut rpd, &surface_view);
+        self.composite_pass.execute(
+            &mut rpd,
+            &self.layer_lighting,
+            self.gi_pass.as_ref().map(|gi_pass| gi_pass.view()),
+            self.ao_pass
+                .as_ref()
+                .map(|ao_pass| (ao_pass.view(), self.layer_occlusion.as_slice())),
+        );
+        if let Some(bloom_pass) = &self.bloom_pass {
+            bloom_pass.execute(&mut rpd, self.composite_pass.view());
         }
+        self.composite_pass.present(
+            &mut rpd,
+            &surface_view,
+            (self.config.width, self.config.height),
+            self.bloom_pass.as_ref().map(|bloom_pass| bloom_pass.view()),
+        );
 
         self.queue.submit(Some(encoder.finish()));
         frame.present();
+        // Runs the callbacks of finished readbacks, waking pending picks
+        let _ = self.device.poll(PollType::Poll);
+
+        for draws in &mut self.mesh_draws {
+            draws.clear();
+        }
+        for draws in &mut self.tilemap_draws {
+            draws.clear();
+        }
+        self.lights.clear();
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
@@ -89,9 +474,31 @@ impl Renderer {
         self.config.height = height;
         self.surface.configure(&self.device, &self.config);
 
-        self.gbuffer = GBuffer::new(&self.device, width, height, LAYERS);
-        self.geometry_pass = passes::Geometry::new(&self.device, &self.gbuffer);
-        self.composite_pass =
-            passes::Composite::new(&self.device, self.config.format, &self.gbuffer, COMPOSITE_MODE);
+        // Only the G-buffer and the bind groups reading it depend on the size, the passes
+        // keep their pipelines. A fixed resolution does not change at all.
+        let size = RESOLUTION.size(width, height);
+        if size == self.gbuffer.size {
+            return;
+        }
+        self.gbuffer = GBuffer::new(
+            &self.device,
+            size.0,
+            size.1,
+            LAYERS,
+            GBUFFER_FORMAT,
+            self.gbuffer.sample_count,
+        );
+        self.sdf_pass.resize(&self.device, &self.gbuffer);
+        self.lighting_pass.resize(&self.device, &self.gbuffer);
+        if let Some(gi_pass) = &mut self.gi_pass {
+            gi_pass.resize(&self.device, &self.gbuffer);
+        }
+        if let Some(ao_pass) = &mut self.ao_pass {
+            ao_pass.resize(&self.device, &self.gbuffer);
+        }
+        self.composite_pass.resize(&self.device, &self.gbuffer);
+        if let Some(bloom_pass) = &mut self.bloom_pass {
+            bloom_pass.resize(&self.device, &self.gbuffer);
+        }
     }
 }
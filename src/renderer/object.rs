@@ -1,9 +1,80 @@
 // Object data structure for rendering
 
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+use super::{
+    color::{srgb_to_linear, srgba_to_linear},
+    material::{MaterialId, DEFAULT_MATERIAL},
+    texture::TextureId,
+};
+
+pub const OBJECT_HIDDEN: u32 = 1 << 0;
+pub const OBJECT_FLIP_X: u32 = 1 << 1;
+pub const OBJECT_FLIP_Y: u32 = 1 << 2;
+/// Ignores the camera, `pos` and `size` are in pixels of the frame.
+pub const OBJECT_SCREEN_SPACE: u32 = 1 << 3;
+
+#[derive(Copy, Clone)]
 pub struct Object {
     pub pos: [f32; 2],
     pub size: [f32; 2],
+    /// sRGB with straight alpha.
     pub color: [f32; 4],
+    /// Light given off regardless of lighting, added on top of the lit color. sRGB.
+    pub emissive: [f32; 3],
+    pub emissive_intensity: f32,
+    pub material: MaterialId,
+    pub flags: u32,
+    /// Tangent-space normal map sampled with the object's uv, green pointing up.
+    /// Objects without one are flat. Created with `Renderer::create_linear_texture`.
+    pub normal_map: Option<TextureId>,
+    /// Written to the layer's depth target, 0.0 nearest and 1.0 farthest. Nearer objects
+    /// cover farther ones whatever order they are drawn in, at equal depth later ones win.
+    pub depth: f32,
+}
+
+impl Default for Object {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            size: [0.0, 0.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            emissive_intensity: 1.0,
+            material: DEFAULT_MATERIAL,
+            flags: 0,
+            normal_map: None,
+            depth: 0.0,
+        }
+    }
+}
+
+// GPU layout of an object, matches `Object` in geometry.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ObjectRaw {
+    pos: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
+    emissive: [f32; 3],
+    flags: u32,
+    id: u32,
+    depth: f32,
+    _pad: [u32; 2],
+}
+
+impl ObjectRaw {
+    // `id` is written to the G-buffer id target, see `picking`
+    pub(crate) fn new(object: &Object, id: u32) -> Self {
+        Self {
+            pos: object.pos,
+            size: object.size,
+            color: srgba_to_linear(object.color),
+            emissive: object
+                .emissive
+                .map(|c| srgb_to_linear(c) * object.emissive_intensity),
+            flags: object.flags,
+            id,
+            depth: object.depth,
+            _pad: [0; 2],
+        }
+    }
 }
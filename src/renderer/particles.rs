@@ -0,0 +1,209 @@
+// Particle emitters simulated on the GPU
+//
+// Particles live in a persistent storage buffer per emitter, used as a ring.
+// The CPU only decides how many particles to spawn each frame, a compute
+// shader initializes and integrates them and the particles pass draws them.
+
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferDescriptor, BufferUsages, Device, Queue,
+};
+
+use super::color::srgba_to_linear;
+
+// Particles simulated per workgroup, matches particles_simulate.wgsl
+pub(crate) const WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EmitterId(pub(crate) u32);
+
+#[derive(Copy, Clone)]
+pub struct EmitterDescriptor {
+    pub pos: [f32; 2],
+    /// Particles spawned per second.
+    pub spawn_rate: f32,
+    /// Seconds a particle lives.
+    pub lifetime: f32,
+    pub velocity: [f32; 2],
+    /// Random offset added to `velocity` per axis, in `-spread..spread`.
+    pub velocity_spread: [f32; 2],
+    pub gravity: [f32; 2],
+    /// sRGB, faded towards `color_end` over the particle's life.
+    pub color_start: [f32; 4],
+    pub color_end: [f32; 4],
+    pub size_start: f32,
+    pub size_end: f32,
+    /// Written to the layer's depth target like `Object::depth`.
+    pub depth: f32,
+}
+
+impl Default for EmitterDescriptor {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            spawn_rate: 10.0,
+            lifetime: 1.0,
+            velocity: [0.0, 0.0],
+            velocity_spread: [0.0, 0.0],
+            gravity: [0.0, 0.0],
+            color_start: [1.0, 1.0, 1.0, 1.0],
+            color_end: [1.0, 1.0, 1.0, 0.0],
+            size_start: 4.0,
+            size_end: 4.0,
+            depth: 0.0,
+        }
+    }
+}
+
+// GPU layout of a particle, matches `Particle` in particles.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleRaw {
+    pos: [f32; 2],
+    vel: [f32; 2],
+    age: f32,
+    lifetime: f32,
+}
+
+// GPU layout of an emitter, matches `Emitter` in particles.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EmitterRaw {
+    pos: [f32; 2],
+    velocity: [f32; 2],
+    velocity_spread: [f32; 2],
+    gravity: [f32; 2],
+    color_start: [f32; 4],
+    color_end: [f32; 4],
+    size_start: f32,
+    size_end: f32,
+    lifetime: f32,
+    dt: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    capacity: u32,
+    seed: u32,
+    depth: f32,
+    _pad: [u32; 3],
+}
+
+pub(crate) struct Emitter {
+    pub layer: u32,
+    pub capacity: u32,
+    pub params_b: Buffer,
+    pub particles_b: Buffer,
+    desc: EmitterDescriptor,
+    spawn_accumulator: f32,
+    cursor: u32,
+}
+
+// Emitters created by the user, simulated and drawn every frame until removed
+#[derive(Default)]
+pub struct Emitters {
+    emitters: Vec<Option<Emitter>>,
+    frame: u32,
+}
+
+impl Emitters {
+    pub fn create(
+        &mut self,
+        device: &Device,
+        layer: u32,
+        capacity: u32,
+        desc: &EmitterDescriptor,
+    ) -> EmitterId {
+        assert!(capacity > 0, "emitters need room for at least one particle");
+        // Simulated in a single dispatch from a single storage binding
+        let limits = device.limits();
+        let capacity = capacity
+            .min(
+                limits
+                    .max_compute_workgroups_per_dimension
+                    .saturating_mul(WORKGROUP_SIZE),
+            )
+            .min(limits.max_storage_buffer_binding_size / size_of::<ParticleRaw>() as u32);
+        let id = EmitterId(self.emitters.len() as u32);
+
+        let params_b = device.create_buffer(&BufferDescriptor {
+            label: Some(&format!("Emitter {} Params B", id.0)),
+            size: std::mem::size_of::<EmitterRaw>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Zeroed particles have no lifetime, so they start out dead
+        let particles_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Emitter {} Particles B", id.0)),
+            contents: bytemuck::cast_slice(&vec![
+                ParticleRaw {
+                    pos: [0.0; 2],
+                    vel: [0.0; 2],
+                    age: 0.0,
+                    lifetime: 0.0,
+                };
+                capacity as usize
+            ]),
+            usage: BufferUsages::STORAGE,
+        });
+
+        self.emitters.push(Some(Emitter {
+            layer,
+            capacity,
+            params_b,
+            particles_b,
+            desc: *desc,
+            spawn_accumulator: 0.0,
+            cursor: 0,
+        }));
+        id
+    }
+
+    // Particles already alive keep their velocity and lifetime
+    pub fn update(&mut self, id: EmitterId, desc: &EmitterDescriptor) {
+        if let Some(emitter) = &mut self.emitters[id.0 as usize] {
+            emitter.desc = *desc;
+        }
+    }
+
+    pub fn remove(&mut self, id: EmitterId) {
+        self.emitters[id.0 as usize] = None;
+    }
+
+    // Decides this frame's spawns and uploads the parameters for the simulation
+    pub fn advance(&mut self, queue: &Queue, dt: f32) {
+        self.frame = self.frame.wrapping_add(1);
+
+        for emitter in self.emitters.iter_mut().flatten() {
+            emitter.spawn_accumulator += emitter.desc.spawn_rate * dt;
+            let spawns = emitter.spawn_accumulator.floor();
+            emitter.spawn_accumulator -= spawns;
+            let spawn_count = (spawns as u32).min(emitter.capacity);
+
+            let desc = &emitter.desc;
+            let raw = EmitterRaw {
+                pos: desc.pos,
+                velocity: desc.velocity,
+                velocity_spread: desc.velocity_spread,
+                gravity: desc.gravity,
+                color_start: srgba_to_linear(desc.color_start),
+                color_end: srgba_to_linear(desc.color_end),
+                size_start: desc.size_start,
+                size_end: desc.size_end,
+                lifetime: desc.lifetime,
+                dt,
+                spawn_start: emitter.cursor,
+                spawn_count,
+                capacity: emitter.capacity,
+                seed: self.frame,
+                depth: desc.depth,
+                _pad: [0; 3],
+            };
+            queue.write_buffer(&emitter.params_b, 0, bytemuck::bytes_of(&raw));
+
+            emitter.cursor = (emitter.cursor + spawn_count) % emitter.capacity;
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Emitter> {
+        self.emitters.iter().flatten()
+    }
+}
@@ -0,0 +1,235 @@
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, BufferBindingType,
+    BufferUsages, Color, ColorTargetState, ColorWrites, Device, Extent3d, FragmentState, LoadOp,
+    MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderStages, StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+use crate::renderer::gbuffer::GBuffer;
+
+use super::RenderPassData;
+
+const AO_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+// Shape of the shade every layer casts, how dark it gets is set per pair of layers
+#[derive(Copy, Clone)]
+pub struct AoSettings {
+    // G-buffer pixels the shade is moved by from the layer casting it, along the world axes
+    pub offset: [i32; 2],
+    // G-buffer pixels the shade is blurred over, 0 leaves a hard copy of the layer's shape
+    pub radius: u32,
+}
+
+// Matches `Params` in ao.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParamsUniform {
+    offset: [i32; 2],
+    radius: u32,
+    layer: u32,
+}
+
+// A texture array with a view of each of its layers to draw into
+struct Targets {
+    view: TextureView,
+    layer_views: Vec<TextureView>,
+}
+
+// Ambient occlusion between layers. Every layer that shades another gets its alpha blurred
+// into a soft shade, which the composite darkens the layers beneath it with.
+pub struct Ao {
+    horizontal_pipeline: RenderPipeline,
+    vertical_pipeline: RenderPipeline,
+    bgl: BindGroupLayout,
+    settings: AoSettings,
+    // Shades blurred along rows only
+    blur: Targets,
+    shade: Targets,
+}
+
+impl Ao {
+    pub fn new(device: &Device, gbuffer: &GBuffer, settings: AoSettings) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("../../shaders/ao.wgsl"));
+
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("AO BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("AO Pipeline Layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(&format!("AO {} Pipeline", entry_point)),
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(ColorTargetState {
+                        format: AO_FORMAT,
+                        blend: Some(BlendState::REPLACE),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        Self {
+            horizontal_pipeline: pipeline("fs_horizontal"),
+            vertical_pipeline: pipeline("fs_vertical"),
+            bgl,
+            settings,
+            blur: Self::create_targets(device, gbuffer, "AO Blur"),
+            shade: Self::create_targets(device, gbuffer, "AO Shade"),
+        }
+    }
+
+    // The targets follow the G-buffer's size, the pipelines are kept
+    pub fn resize(&mut self, device: &Device, gbuffer: &GBuffer) {
+        self.blur = Self::create_targets(device, gbuffer, "AO Blur");
+        self.shade = Self::create_targets(device, gbuffer, "AO Shade");
+    }
+
+    fn create_targets(device: &Device, gbuffer: &GBuffer, label: &str) -> Targets {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width: gbuffer.size.0,
+                height: gbuffer.size.1,
+                depth_or_array_layers: gbuffer.layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: AO_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..gbuffer.layers)
+            .map(|layer| {
+                texture.create_view(&TextureViewDescriptor {
+                    dimension: Some(TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        Targets { view, layer_views }
+    }
+
+    // Shade each layer casts, only written for the layers shading another
+    pub fn view(&self) -> &TextureView {
+        &self.shade.view
+    }
+
+    // `strengths` holds how strongly each layer shades each one beneath it, at
+    // `caster * layers + receiver`
+    pub fn execute(&self, data: &mut RenderPassData, strengths: &[f32]) {
+        let layers = data.gbuffer.layers;
+        let casts = |caster: u32| {
+            (0..caster).any(|receiver| strengths[(caster * layers + receiver) as usize] > 0.0)
+        };
+
+        for caster in (0..layers).filter(|&caster| casts(caster)) {
+            let params_b = data.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("AO Params B"),
+                contents: bytemuck::bytes_of(&ParamsUniform {
+                    offset: self.settings.offset,
+                    radius: self.settings.radius,
+                    layer: caster,
+                }),
+                usage: BufferUsages::UNIFORM,
+            });
+            let steps = [
+                (
+                    &self.horizontal_pipeline,
+                    &data.gbuffer.color_view,
+                    &self.blur.layer_views[caster as usize],
+                ),
+                (
+                    &self.vertical_pipeline,
+                    &self.blur.view,
+                    &self.shade.layer_views[caster as usize],
+                ),
+            ];
+
+            for (pipeline, input, output) in steps {
+                let bind_group = data.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("AO BG"),
+                    layout: &self.bgl,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(input),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: params_b.as_entire_binding(),
+                        },
+                    ],
+                });
+                let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("AO Pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: output,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Color::BLACK),
+                            store: StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    })],
+                    ..Default::default()
+                });
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, &bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            }
+        }
+    }
+}
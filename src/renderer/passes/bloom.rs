@@ -0,0 +1,289 @@
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor,
+    BlendOperation, BlendState, Buffer, BufferBindingType, BufferUsages, Color, ColorTargetState,
+    ColorWrites, Device, Extent3d, FilterMode, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderStages, StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDimension, VertexState,
+};
+
+use crate::renderer::gbuffer::GBuffer;
+
+use super::RenderPassData;
+
+const BLOOM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+// What glows, before the threshold is applied
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum BloomSource {
+    // The composited scene, lit colors included
+    Scene = 0,
+    // Only emissive pixels not covered by the layers above them
+    Emissive = 1,
+}
+
+#[derive(Copy, Clone)]
+pub struct BloomSettings {
+    pub source: BloomSource,
+    // Luminance a pixel has to exceed to glow, 1.0 only lets HDR G-buffers glow
+    pub threshold: f32,
+    // Brightness of the glow added to the scene
+    pub intensity: f32,
+    // How far the glow spreads in G-buffer pixels, rounded up to a power of two as every
+    // level of the blur chain doubles it
+    pub radius: f32,
+}
+
+// Matches `Params` in bloom.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParamsUniform {
+    threshold: f32,
+    intensity: f32,
+    source: u32,
+    _pad: u32,
+}
+
+// Glow around the bright pixels of the composited scene, blurred by halving it level by
+// level and adding the levels back up. The result has half the G-buffer's size and is added
+// to the scene by `Composite::present`.
+pub struct Bloom {
+    prefilter_pipeline: RenderPipeline,
+    downsample_pipeline: RenderPipeline,
+    upsample_pipeline: RenderPipeline,
+    bgl: BindGroupLayout,
+    sampler: Sampler,
+    params_b: Buffer,
+    settings: BloomSettings,
+    // Largest level first
+    level_views: Vec<TextureView>,
+}
+
+impl Bloom {
+    pub fn new(device: &Device, gbuffer: &GBuffer, settings: BloomSettings) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("../../shaders/bloom.wgsl"));
+
+        let texture = |binding, view_dimension| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Bloom BGL"),
+            entries: &[
+                texture(0, TextureViewDimension::D2),
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(3, TextureViewDimension::D2Array),
+                texture(4, TextureViewDimension::D2Array),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str, blend| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(&format!("Bloom {} Pipeline", entry_point)),
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(ColorTargetState {
+                        format: BLOOM_FORMAT,
+                        blend: Some(blend),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let additive = BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation: BlendOperation::Add,
+        };
+
+        // Clamped so the taps past the edges repeat them instead of wrapping around
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let params_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Bloom Params B"),
+            contents: bytemuck::bytes_of(&ParamsUniform {
+                threshold: settings.threshold,
+                intensity: settings.intensity,
+                source: settings.source as u32,
+                _pad: 0,
+            }),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        Self {
+            prefilter_pipeline: pipeline("fs_prefilter", BlendState::REPLACE),
+            downsample_pipeline: pipeline("fs_downsample", BlendState::REPLACE),
+            upsample_pipeline: pipeline(
+                "fs_upsample",
+                BlendState {
+                    color: additive,
+                    alpha: additive,
+                },
+            ),
+            bgl,
+            sampler,
+            params_b,
+            settings,
+            level_views: Self::create_levels(device, gbuffer, settings),
+        }
+    }
+
+    // The levels follow the G-buffer's size, the pipelines are kept
+    pub fn resize(&mut self, device: &Device, gbuffer: &GBuffer) {
+        self.level_views = Self::create_levels(device, gbuffer, self.settings);
+    }
+
+    fn create_levels(
+        device: &Device,
+        gbuffer: &GBuffer,
+        settings: BloomSettings,
+    ) -> Vec<TextureView> {
+        // The first level already spreads the glow over two pixels, none gets below one
+        let (mut width, mut height) = (gbuffer.size.0.div_ceil(2), gbuffer.size.1.div_ceil(2));
+        let wanted = settings.radius.max(2.0).log2().ceil() as u32;
+        let fitting = width.min(height).max(1).ilog2() + 1;
+
+        let mut levels = Vec::new();
+        for i in 0..wanted.min(fitting) {
+            let view = device
+                .create_texture(&TextureDescriptor {
+                    label: Some(&format!("Bloom Level {}", i)),
+                    size: Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: BLOOM_FORMAT,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&Default::default());
+            levels.push(view);
+            (width, height) = (width.div_ceil(2), height.div_ceil(2));
+        }
+        levels
+    }
+
+    // Glow to add to the scene, covering the whole G-buffer
+    pub fn view(&self) -> &TextureView {
+        &self.level_views[0]
+    }
+
+    // `scene` is the composited scene
+    pub fn execute(&self, data: &mut RenderPassData, scene: &TextureView) {
+        let bind_group = |input: &TextureView| {
+            data.device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Bloom BG"),
+                layout: &self.bgl,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(input),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: self.params_b.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(&data.gbuffer.color_view),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::TextureView(&data.gbuffer.emissive_view),
+                    },
+                ],
+            })
+        };
+
+        // Each level is drawn from the one above it on the way down, and has the one below
+        // it added on the way up
+        let levels = &self.level_views;
+        let mut steps = vec![(&self.prefilter_pipeline, scene, &levels[0], false)];
+        for i in 1..levels.len() {
+            steps.push((&self.downsample_pipeline, &levels[i - 1], &levels[i], false));
+        }
+        for i in (1..levels.len()).rev() {
+            steps.push((&self.upsample_pipeline, &levels[i], &levels[i - 1], true));
+        }
+
+        for (pipeline, input, output, add) in steps {
+            let bind_group = bind_group(input);
+            let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("Bloom Pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: if add {
+                            LoadOp::Load
+                        } else {
+                            LoadOp::Clear(Color::BLACK)
+                        },
+                        store: StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                ..Default::default()
+            });
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
@@ -1,15 +1,17 @@
 use wgpu::{
     include_wgsl,
     util::{BufferInitDescriptor, DeviceExt},
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Color, ColorTargetState,
-    ColorWrites, Device, FragmentState, LoadOp, MultisampleState, Operations,
-    PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
-    RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderStages,
-    StoreOp, TextureFormat, TextureSampleType, TextureView, TextureViewDimension, VertexState,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferDescriptor,
+    BufferUsages, Color, ColorTargetState, ColorWrites, Device, Extent3d, FilterMode,
+    FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, StoreOp, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
 };
 
-use crate::renderer::gbuffer::GBuffer;
+use crate::renderer::{color::srgba_to_linear, gbuffer::GBuffer, light::LayerLighting};
 
 use super::RenderPassData;
 
@@ -17,8 +19,50 @@ use super::RenderPassData;
 pub enum CompositeMode {
     Composite = 0,
     Grid = 1,
+    // Grid view of the normal targets
+    Normals = 2,
+    // Grid view of the emissive targets
+    Emissive = 3,
+    // Grid view of the depth targets, near is dark
+    Depth = 4,
+    // Grid view of the light reaching each layer
+    Light = 5,
 }
 
+// How the G-buffer is fitted into the surface when their sizes differ, keeping its aspect
+// ratio with black bars around it
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum Upscale {
+    // Nearest filtering at the largest whole multiple that fits, for crisp pixel art
+    Pixel,
+    // Linear filtering at the largest size that fits
+    Smooth,
+}
+
+impl Upscale {
+    // Surface rectangle the G-buffer is drawn to, as x, y, width and height
+    pub fn viewport(self, gbuffer: (u32, u32), surface: (u32, u32)) -> [f32; 4] {
+        let fit = (surface.0 as f32 / gbuffer.0 as f32).min(surface.1 as f32 / gbuffer.1 as f32);
+        let scale = match self {
+            // Surfaces smaller than the G-buffer leave no whole multiple, those are shrunk to fit
+            // and the nearest filtering skips some of its rows and columns
+            Upscale::Pixel if fit >= 1.0 => fit.floor(),
+            _ => fit,
+        };
+        let width = gbuffer.0 as f32 * scale;
+        let height = gbuffer.1 as f32 * scale;
+        // Whole pixel offsets keep the nearest filtering aligned with the surface
+        let x = ((surface.0 as f32 - width) / 2.0).round();
+        let y = ((surface.1 as f32 - height) / 2.0).round();
+        [x, y, width, height]
+    }
+}
+
+// Layers are composited into a target of the G-buffer's size, linear and unclamped so bloom
+// can find the bright pixels, before it is presented to the surface
+const SCENE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct ParamsUniform {
@@ -28,17 +72,48 @@ struct ParamsUniform {
 
 impl ParamsUniform {
     fn new(mode: CompositeMode) -> Self {
+        let background = srgba_to_linear([0.1, 0.1, 0.1, 1.0]);
         Self {
-            background: [0.1, 0.1, 0.1],
+            background: [background[0], background[1], background[2]],
             mode: mode as u32,
         }
     }
 }
 
+// Matches `Params` in present.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PresentUniform {
+    tonemap: u32,
+    encode_srgb: u32,
+    _pad: [u32; 2],
+}
+
 pub struct Composite {
     pipeline: RenderPipeline,
+    present_pipeline: RenderPipeline,
+    present_bgl: BindGroupLayout,
+    present_b: Buffer,
+    scene_view: TextureView,
+    gbuffer_bgl: BindGroupLayout,
+    sampler: Sampler,
+    depth_sampler: Sampler,
     gbuffer_bg: BindGroup,
     params_bg: BindGroup,
+    // Light mask of each layer, unlit layers are composited without their light target
+    masks_b: Buffer,
+    // How strongly each layer shades each one beneath it, zero without ambient occlusion
+    occlusion_b: Buffer,
+    indirect_bgl: BindGroupLayout,
+    // Indirect light and bloom are coarser than the G-buffer, they are always filtered
+    linear_sampler: Sampler,
+    // Stands in for the indirect light without global illumination, and for bloom
+    black_view: TextureView,
+    occlusion_bgl: BindGroupLayout,
+    // Stands in for the shades without ambient occlusion
+    black_array_view: TextureView,
+    upscale: Upscale,
+    gbuffer_size: (u32, u32),
     _layers: u32,
 }
 
@@ -48,9 +123,24 @@ impl Composite {
         format: TextureFormat,
         gbuffer: &GBuffer,
         mode: CompositeMode,
+        upscale: Upscale,
     ) -> Self {
         let shader = device.create_shader_module(include_wgsl!("../../shaders/composite.wgsl"));
-        let sampler = device.create_sampler(&SamplerDescriptor::default());
+        let filter = match upscale {
+            Upscale::Pixel => FilterMode::Nearest,
+            Upscale::Smooth => FilterMode::Linear,
+        };
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Composite Sampler"),
+            mag_filter: filter,
+            min_filter: filter,
+            ..Default::default()
+        });
+        // Depth cannot be filtered, it is read with a nearest sampler of its own
+        let depth_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Composite Depth Sampler"),
+            ..Default::default()
+        });
 
         let gbuffer_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Composite GBuffer BGL"),
@@ -71,54 +161,215 @@ impl Composite {
                     },
                     count: None,
                 },
-            ],
-        });
-        let gbuffer_bg = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Composite Grid BG"),
-            layout: &gbuffer_bgl,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::Sampler(&sampler),
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
                 },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(&gbuffer.color_view),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        // Depth formats allow this too, GLSL has no depth reads without a compare
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
                 },
             ],
         });
+        let gbuffer_bg =
+            Self::create_gbuffer_bg(device, &gbuffer_bgl, &sampler, &depth_sampler, gbuffer);
 
+        // Only the composited layers of an HDR G-buffer need tonemapping, and surfaces
+        // without an sRGB format get the encoding done in the shader
+        let present = PresentUniform {
+            tonemap: (gbuffer.is_hdr() && matches!(mode, CompositeMode::Composite)) as u32,
+            encode_srgb: !format.is_srgb() as u32,
+            _pad: [0; 2],
+        };
         let params = ParamsUniform::new(mode);
         let params_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Composite Params BGL"),
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
         let params_b = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Composite Params B"),
             contents: bytemuck::bytes_of(&params),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let masks_b = device.create_buffer(&BufferDescriptor {
+            label: Some("Composite Masks B"),
+            size: gbuffer.layers as u64 * 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let occlusion_b = device.create_buffer(&BufferDescriptor {
+            label: Some("Composite Occlusion B"),
+            size: (gbuffer.layers * gbuffer.layers) as u64 * 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         let params_bg = device.create_bind_group(&BindGroupDescriptor {
             label: Some("Composite Params BG"),
             layout: &params_bgl,
-            entries: &[BindGroupEntry {
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: params_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: masks_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: occlusion_b.as_entire_binding(),
+                },
+            ],
+        });
+
+        let linear_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Composite Linear Sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let indirect_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Composite Indirect BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        // Textures start out zeroed
+        let black_view = device
+            .create_texture(&TextureDescriptor {
+                label: Some("Composite Black Texture"),
+                size: Default::default(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default());
+
+        let occlusion_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Composite Occlusion BGL"),
+            entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                resource: params_b.as_entire_binding(),
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: TextureViewDimension::D2Array,
+                    sample_type: TextureSampleType::Float { filterable: true },
+                },
+                count: None,
             }],
         });
+        // GL makes textures of a single layer plain 2D ones, which arrays cannot sample
+        let black_array_view = device
+            .create_texture(&TextureDescriptor {
+                label: Some("Composite Black Array"),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 2,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor {
+                dimension: Some(TextureViewDimension::D2Array),
+                ..Default::default()
+            });
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Composite PL"),
-            bind_group_layouts: &[&gbuffer_bgl, &params_bgl],
+            bind_group_layouts: &[&gbuffer_bgl, &params_bgl, &indirect_bgl, &occlusion_bgl],
             push_constant_ranges: &[],
         });
 
@@ -134,6 +385,79 @@ impl Composite {
             fragment: Some(FragmentState {
                 module: &shader,
                 entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format: SCENE_FORMAT,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let present_shader =
+            device.create_shader_module(include_wgsl!("../../shaders/present.wgsl"));
+        let present_texture = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2,
+                sample_type: TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let present_sampler = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+        let present_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Present BGL"),
+            entries: &[
+                present_texture(0),
+                present_sampler(1),
+                present_texture(2),
+                present_sampler(3),
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let present_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Present B"),
+            contents: bytemuck::bytes_of(&present),
+            usage: BufferUsages::UNIFORM,
+        });
+        let present_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Present PL"),
+            bind_group_layouts: &[&present_bgl],
+            push_constant_ranges: &[],
+        });
+        let present_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Present P"),
+            layout: Some(&present_pipeline_layout),
+            vertex: VertexState {
+                module: &present_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &present_shader,
+                entry_point: Some("fs_main"),
                 targets: &[Some(ColorTargetState {
                     format,
                     blend: Some(BlendState::REPLACE),
@@ -150,17 +474,155 @@ impl Composite {
 
         Self {
             pipeline,
+            present_pipeline,
+            present_bgl,
+            present_b,
+            scene_view: Self::create_scene(device, gbuffer),
+            gbuffer_bgl,
+            sampler,
+            depth_sampler,
             gbuffer_bg,
             params_bg,
+            masks_b,
+            occlusion_b,
+            indirect_bgl,
+            linear_sampler,
+            black_view,
+            occlusion_bgl,
+            black_array_view,
+            upscale,
+            gbuffer_size: gbuffer.size,
             _layers: gbuffer.layers,
         }
     }
 
-    pub fn execute(&self, data: &mut RenderPassData, view: &TextureView) {
+    // Points the pass at a new G-buffer of the same formats, the pipeline is kept
+    pub fn resize(&mut self, device: &Device, gbuffer: &GBuffer) {
+        self.gbuffer_bg = Self::create_gbuffer_bg(
+            device,
+            &self.gbuffer_bgl,
+            &self.sampler,
+            &self.depth_sampler,
+            gbuffer,
+        );
+        self.scene_view = Self::create_scene(device, gbuffer);
+        self.gbuffer_size = gbuffer.size;
+    }
+
+    fn create_scene(device: &Device, gbuffer: &GBuffer) -> TextureView {
+        device
+            .create_texture(&TextureDescriptor {
+                label: Some("Composite Scene"),
+                size: Extent3d {
+                    width: gbuffer.size.0,
+                    height: gbuffer.size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: SCENE_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    }
+
+    // The composited layers at the G-buffer's size, rows flipped like on the surface
+    pub fn view(&self) -> &TextureView {
+        &self.scene_view
+    }
+
+    fn create_gbuffer_bg(
+        device: &Device,
+        gbuffer_bgl: &BindGroupLayout,
+        sampler: &Sampler,
+        depth_sampler: &Sampler,
+        gbuffer: &GBuffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Composite Grid BG"),
+            layout: gbuffer_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Sampler(sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&gbuffer.color_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&gbuffer.normal_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&gbuffer.emissive_view),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&gbuffer.depth_view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Sampler(depth_sampler),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&gbuffer.light_view),
+                },
+            ],
+        })
+    }
+
+    pub fn viewport(&self, surface_size: (u32, u32)) -> [f32; 4] {
+        self.upscale.viewport(self.gbuffer_size, surface_size)
+    }
+
+    // Composites the layers into the scene target, shown by `present`. `occlusion` is the
+    // shade each layer casts with the strengths it is applied at, see `Ao::execute`.
+    pub fn execute(
+        &self,
+        data: &mut RenderPassData,
+        layers: &[LayerLighting],
+        indirect: Option<&TextureView>,
+        occlusion: Option<(&TextureView, &[f32])>,
+    ) {
+        let masks: Vec<u32> = layers.iter().map(|layer| layer.mask).collect();
+        data.queue
+            .write_buffer(&self.masks_b, 0, bytemuck::cast_slice(&masks));
+        let no_strengths = vec![0.0; layers.len() * layers.len()];
+        let (shades, strengths) = occlusion.unwrap_or((&self.black_array_view, &no_strengths));
+        data.queue
+            .write_buffer(&self.occlusion_b, 0, bytemuck::cast_slice(strengths));
+        let occlusion_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Composite Occlusion BG"),
+            layout: &self.occlusion_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(shades),
+            }],
+        });
+        let indirect_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Composite Indirect BG"),
+            layout: &self.indirect_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(indirect.unwrap_or(&self.black_view)),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.linear_sampler),
+                },
+            ],
+        });
+
         let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Composite Grid Pass"),
             color_attachments: &[Some(RenderPassColorAttachment {
-                view,
+                view: &self.scene_view,
                 resolve_target: None,
                 ops: Operations {
                     load: LoadOp::Clear(Color::BLACK),
@@ -174,6 +636,64 @@ impl Composite {
         rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(0, &self.gbuffer_bg, &[]);
         rpass.set_bind_group(1, &self.params_bg, &[]);
+        rpass.set_bind_group(2, &indirect_bg, &[]);
+        rpass.set_bind_group(3, &occlusion_bg, &[]);
         rpass.draw(0..6, 0..1);
     }
+
+    // Fits the scene into the surface, adding `bloom` on top before tonemapping
+    pub fn present(
+        &self,
+        data: &mut RenderPassData,
+        view: &TextureView,
+        surface_size: (u32, u32),
+        bloom: Option<&TextureView>,
+    ) {
+        let present_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Present BG"),
+            layout: &self.present_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&self.scene_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(bloom.unwrap_or(&self.black_view)),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Sampler(&self.linear_sampler),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: self.present_b.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Present Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+
+        let [x, y, width, height] = self.viewport(surface_size);
+        rpass.set_viewport(x, y, width, height, 0.0, 1.0);
+        rpass.set_pipeline(&self.present_pipeline);
+        rpass.set_bind_group(0, &present_bg, &[]);
+        rpass.draw(0..3, 0..1);
+    }
 }
@@ -1,30 +1,50 @@
 use wgpu::{
-    include_wgsl,
-    util::{BufferInitDescriptor, DeviceExt},
+    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsages, Color,
-    ColorTargetState, ColorWrites, CompareFunction, DepthStencilState, Device, FragmentState,
-    LoadOp, Operations, PipelineLayoutDescriptor, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, ShaderStages, StoreOp, VertexState,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBindingType,
+    BufferUsages, ColorTargetState, CompareFunction, DepthStencilState, Device, Extent3d,
+    FragmentState, MultisampleState, PipelineLayoutDescriptor, Queue, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension,
+    VertexState,
 };
 
-use crate::renderer::{camera::Camera, gbuffer::GBuffer, object::Object};
+use crate::renderer::{
+    camera::Camera,
+    gbuffer::GBuffer,
+    material::{MaterialDescriptor, MaterialId, DEFAULT_MATERIAL, DEFAULT_MATERIAL_SOURCE},
+    object::{Object, ObjectRaw},
+    picking,
+    texture::Textures,
+};
 
 use super::RenderPassData;
 
-pub struct Geometry {
+const GEOMETRY_SOURCE: &str = include_str!("../../shaders/geometry.wgsl");
+
+struct Material {
     pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    uniform_b: Option<Buffer>,
+}
+
+pub struct Geometry {
+    materials: Vec<Material>,
     objects_bgl: BindGroupLayout,
-    params_bg: BindGroup,
+    params_bgl: BindGroupLayout,
     frame_b: Buffer,
     camera_b: Buffer,
+    normal_bgl: BindGroupLayout,
+    normal_sampler: Sampler,
+    flat_normal_bg: BindGroup,
+    color_targets: Vec<Option<ColorTargetState>>,
+    depth_format: TextureFormat,
+    multisample: MultisampleState,
 }
 
 impl Geometry {
-    pub fn new(device: &Device, gbuffer: &GBuffer) -> Self {
-        let shader = device.create_shader_module(include_wgsl!("../../shaders/geometry.wgsl"));
-
+    pub fn new(device: &Device, queue: &Queue, gbuffer: &GBuffer, textures: &Textures) -> Self {
         let objects_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("Object Bind Group Layout"),
             entries: &[BindGroupLayoutEntry {
@@ -76,31 +96,204 @@ impl Geometry {
                     },
                     count: None,
                 },
+                // The G-buffer's SDF for materials
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
             ],
         });
-        let params_bg = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Params BG"),
-            layout: &params_bgl,
+
+        let normal_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Normal Map BGL"),
             entries: &[
-                BindGroupEntry {
+                BindGroupLayoutEntry {
                     binding: 0,
-                    resource: frame_b.as_entire_binding(),
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
                 },
-                BindGroupEntry {
+                BindGroupLayoutEntry {
                     binding: 1,
-                    resource: camera_b.as_entire_binding(),
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
                 },
             ],
         });
+        let normal_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Normal Map Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Bound for objects without a normal map, faces straight at the viewer
+        let flat_normal = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Flat Normal Texture"),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &[128, 128, 255, 255],
+        );
+        let flat_normal_view = flat_normal.create_view(&Default::default());
+        let flat_normal_bg =
+            Self::create_normal_bg(device, &normal_bgl, &normal_sampler, &flat_normal_view);
+
+        let mut geometry = Self {
+            materials: Vec::new(),
+            objects_bgl,
+            params_bgl,
+            frame_b,
+            camera_b,
+            normal_bgl,
+            normal_sampler,
+            flat_normal_bg,
+            color_targets: gbuffer.color_targets(BlendState::REPLACE, true).to_vec(),
+            depth_format: gbuffer.depth_format,
+            multisample: gbuffer.multisample(),
+        };
+
+        let default_material = geometry.register_material(
+            device,
+            textures,
+            &MaterialDescriptor {
+                label: "Default",
+                source: DEFAULT_MATERIAL_SOURCE,
+                uniform: None,
+                textures: &[],
+            },
+        );
+        debug_assert_eq!(default_material, DEFAULT_MATERIAL);
+
+        geometry
+    }
+
+    pub fn register_material(
+        &mut self,
+        device: &Device,
+        textures: &Textures,
+        desc: &MaterialDescriptor,
+    ) -> MaterialId {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(&format!("Material {} Shader", desc.label)),
+            source: ShaderSource::Wgsl(format!("{}\n{}", GEOMETRY_SOURCE, desc.source).into()),
+        });
+
+        let mut layout_entries = Vec::new();
+        if desc.uniform.is_some() {
+            layout_entries.push(BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+        }
+        if !desc.textures.is_empty() {
+            layout_entries.push(BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        for i in 0..desc.textures.len() {
+            layout_entries.push(BindGroupLayoutEntry {
+                binding: 2 + i as u32,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: TextureViewDimension::D2,
+                    sample_type: TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            });
+        }
+        let material_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(&format!("Material {} BGL", desc.label)),
+            entries: &layout_entries,
+        });
+
+        let uniform_b = desc.uniform.map(|contents| {
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(&format!("Material {} Uniform B", desc.label)),
+                contents,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            })
+        });
+        let sampler = (!desc.textures.is_empty()).then(|| {
+            device.create_sampler(&SamplerDescriptor {
+                label: Some(&format!("Material {} Sampler", desc.label)),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            })
+        });
+
+        let mut entries = Vec::new();
+        if let Some(uniform_b) = &uniform_b {
+            entries.push(BindGroupEntry {
+                binding: 0,
+                resource: uniform_b.as_entire_binding(),
+            });
+        }
+        if let Some(sampler) = &sampler {
+            entries.push(BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            });
+        }
+        for (i, texture) in desc.textures.iter().enumerate() {
+            entries.push(BindGroupEntry {
+                binding: 2 + i as u32,
+                resource: BindingResource::TextureView(textures.view(*texture)),
+            });
+        }
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&format!("Material {} BG", desc.label)),
+            layout: &material_bgl,
+            entries: &entries,
+        });
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Geometry Pipeline Layout"),
-            bind_group_layouts: &[&objects_bgl, &params_bgl],
+            label: Some(&format!("Material {} Pipeline Layout", desc.label)),
+            bind_group_layouts: &[
+                &self.objects_bgl,
+                &self.params_bgl,
+                &material_bgl,
+                &self.normal_bgl,
+            ],
             push_constant_ranges: &[],
         });
 
         let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Geometry Pipeline"),
+            label: Some(&format!("Material {} Pipeline", desc.label)),
             layout: Some(&pipeline_layout),
             vertex: VertexState {
                 module: &shader,
@@ -111,32 +304,75 @@ impl Geometry {
             fragment: Some(FragmentState {
                 module: &shader,
                 entry_point: Some("fs_main"),
-                targets: &[Some(ColorTargetState {
-                    format: gbuffer.format,
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::ALL,
-                })],
+                targets: &self.color_targets,
                 compilation_options: Default::default(),
             }),
             primitive: Default::default(),
             depth_stencil: Some(DepthStencilState {
-                format: gbuffer.depth_format,
-                depth_write_enabled: false,
-                depth_compare: CompareFunction::Less,
+                format: self.depth_format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::LessEqual,
                 stencil: Default::default(),
                 bias: Default::default(),
             }),
-            multisample: Default::default(),
+            multisample: self.multisample,
             multiview: None,
             cache: None,
         });
 
-        Self {
+        self.materials.push(Material {
             pipeline,
-            objects_bgl,
-            params_bg,
-            frame_b,
-            camera_b,
+            bind_group,
+            uniform_b,
+        });
+        (self.materials.len() - 1) as MaterialId
+    }
+
+    // Updates to unknown materials or ones without a uniform are ignored, as unknown
+    // materials are when drawing, debug builds catch them
+    pub fn update_material(&self, queue: &Queue, material: MaterialId, uniform: &[u8]) {
+        let uniform_b = self
+            .materials
+            .get(material as usize)
+            .and_then(|material| material.uniform_b.as_ref());
+        debug_assert!(
+            uniform_b.is_some(),
+            "material {} is unknown or was registered without a uniform",
+            material
+        );
+        if let Some(uniform_b) = uniform_b {
+            queue.write_buffer(uniform_b, 0, uniform);
+        }
+    }
+
+    fn create_normal_bg(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        view: &TextureView,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Normal Map BG"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Sampler(sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(view),
+                },
+            ],
+        })
+    }
+
+    // Unknown materials are drawn with the default one
+    fn resolve_material(&self, material: MaterialId) -> MaterialId {
+        if (material as usize) < self.materials.len() {
+            material
+        } else {
+            DEFAULT_MATERIAL
         }
     }
 
@@ -147,17 +383,53 @@ impl Geometry {
         layer: u32,
         camera: &Camera,
     ) {
+        if objects.is_empty() {
+            return;
+        }
+
         data.queue
             .write_buffer(&self.camera_b, 0, bytemuck::cast_slice(&[*camera]));
         let frame = data.gbuffer.frame();
         data.queue
             .write_buffer(&self.frame_b, 0, bytemuck::cast_slice(&[frame]));
 
+        // Objects are drawn in the order given, later ones on top, so only neighbours sharing
+        // a material and normal map are batched. Objects keep their index in `objects` for
+        // picking.
+        let resolved: Vec<(MaterialId, u32, &Object)> = objects
+            .iter()
+            .enumerate()
+            .map(|(index, object)| (self.resolve_material(object.material), index as u32, object))
+            .collect();
+        let raw: Vec<ObjectRaw> = resolved
+            .iter()
+            .map(|(_, index, object)| ObjectRaw::new(object, picking::encode_id(layer, *index)))
+            .collect();
+
         let objects_b = data.device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Object Buffer"),
-            contents: bytemuck::cast_slice(objects),
+            contents: bytemuck::cast_slice(&raw),
             usage: BufferUsages::STORAGE,
         });
+        // Created every frame as the SDF is replaced with the G-buffer
+        let params_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Params BG"),
+            layout: &self.params_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.frame_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.camera_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&data.gbuffer.sdf_view),
+                },
+            ],
+        });
         let objects_bg = data.device.create_bind_group(&BindGroupDescriptor {
             label: Some("Object Bind Group"),
             layout: &self.objects_bgl,
@@ -167,34 +439,45 @@ impl Geometry {
             }],
         });
 
-        let color_view = &data.gbuffer.color_layer_view(layer);
-        let depth_view = &data.gbuffer.depth_layer_view(layer);
+        let runs: Vec<&[(MaterialId, u32, &Object)]> = resolved
+            .chunk_by(|a, b| a.0 == b.0 && a.2.normal_map == b.2.normal_map)
+            .collect();
+        let normal_bgs: Vec<Option<BindGroup>> = runs
+            .iter()
+            .map(|run| {
+                run[0].2.normal_map.map(|normal_map| {
+                    Self::create_normal_bg(
+                        data.device,
+                        &self.normal_bgl,
+                        &self.normal_sampler,
+                        data.textures.view(normal_map),
+                    )
+                })
+            })
+            .collect();
 
+        let views = data.gbuffer.layer_views(layer);
         let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some(&format!("Geometry Pass Layer {}", layer)),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: color_view,
-                resolve_target: None,
-                ops: Operations {
-                    load: LoadOp::Clear(Color::TRANSPARENT),
-                    store: StoreOp::Store,
-                },
-                depth_slice: None,
-            })],
-            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                view: depth_view,
-                depth_ops: Some(Operations {
-                    load: LoadOp::Clear(1.0),
-                    store: StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
+            color_attachments: &views.color_attachments(),
+            depth_stencil_attachment: Some(views.depth_attachment()),
             ..Default::default()
         });
 
-        rpass.set_pipeline(&self.pipeline);
         rpass.set_bind_group(0, &objects_bg, &[]);
-        rpass.set_bind_group(1, &self.params_bg, &[]);
-        rpass.draw(0..6, 0..objects.len() as u32);
+        rpass.set_bind_group(1, &params_bg, &[]);
+
+        let mut start = 0;
+        for (run, normal_bg) in runs.iter().zip(&normal_bgs) {
+            let material = &self.materials[run[0].0 as usize];
+            let end = start + run.len() as u32;
+
+            rpass.set_pipeline(&material.pipeline);
+            rpass.set_bind_group(2, &material.bind_group, &[]);
+            rpass.set_bind_group(3, normal_bg.as_ref().unwrap_or(&self.flat_normal_bg), &[]);
+            rpass.draw(0..6, start..end);
+
+            start = end;
+        }
     }
 }
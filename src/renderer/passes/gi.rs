@@ -0,0 +1,327 @@
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, Extent3d,
+    PipelineLayoutDescriptor, ShaderStages, StorageTextureAccess, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDimension,
+};
+
+use crate::renderer::{
+    gbuffer::GBuffer,
+    light::{occluder_mask, LayerLighting},
+};
+
+use super::RenderPassData;
+
+const WORKGROUP_SIZE: u32 = 8;
+const GI_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+// Matches `PROBE_SPACING` in gi.wgsl
+const PROBE_SPACING: u32 = 2;
+
+// Quality of the global illumination, both settings trade detail for speed
+#[derive(Copy, Clone)]
+pub struct GiSettings {
+    // Every cascade quadruples how far light travels, 5 reaches about 700 scene pixels.
+    // Clamped to the cascades reaching across the scene.
+    pub cascades: u32,
+    // Size of the scene the rays march through relative to the G-buffer, rays take a step
+    // per scene pixel so this sets most of the cost
+    pub scale: f32,
+    // Share of the direct light occluders reflect onto their surroundings
+    pub bounce: f32,
+}
+
+// Matches `Params` in gi.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParamsUniform {
+    occluders: u32,
+    lit: u32,
+    cascade: u32,
+    cascade_count: u32,
+    bounce: f32,
+    _pad: [u32; 3],
+}
+
+// Indirect light from radiance cascades. Emissive pixels and lit occluders are the only
+// things light bounces off, every other pixel lets rays through, so the floor is brightened
+// by what glows or is lit around it. The result is the light reaching each cascade 0 probe,
+// added to the lit layers by the composite.
+pub struct Gi {
+    scene_pipeline: ComputePipeline,
+    cascade_pipeline: ComputePipeline,
+    integrate_pipeline: ComputePipeline,
+    bgl: BindGroupLayout,
+    settings: GiSettings,
+    scene_view: TextureView,
+    // Lowest cascade first
+    cascade_views: Vec<TextureView>,
+    irradiance_view: TextureView,
+}
+
+impl Gi {
+    pub fn new(device: &Device, gbuffer: &GBuffer, settings: GiSettings) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("../../shaders/gi.wgsl"));
+
+        let array = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2Array,
+                sample_type: TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let texture = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                multisampled: false,
+                view_dimension: TextureViewDimension::D2,
+                sample_type: TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("GI BGL"),
+            entries: &[
+                array(0),
+                array(1),
+                array(2),
+                texture(3),
+                texture(4),
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: GI_FORMAT,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("GI Pipeline Layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(&format!("GI {} Pipeline", entry_point)),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        let (scene_view, cascade_views, irradiance_view) =
+            Self::create_textures(device, gbuffer, settings);
+        Self {
+            scene_pipeline: pipeline("scene"),
+            cascade_pipeline: pipeline("cascade"),
+            integrate_pipeline: pipeline("integrate"),
+            bgl,
+            settings,
+            scene_view,
+            cascade_views,
+            irradiance_view,
+        }
+    }
+
+    // The textures follow the G-buffer's size, the pipelines are kept
+    pub fn resize(&mut self, device: &Device, gbuffer: &GBuffer) {
+        (self.scene_view, self.cascade_views, self.irradiance_view) =
+            Self::create_textures(device, gbuffer, self.settings);
+    }
+
+    fn create_textures(
+        device: &Device,
+        gbuffer: &GBuffer,
+        settings: GiSettings,
+    ) -> (TextureView, Vec<TextureView>, TextureView) {
+        let texture = |label: &str, width: u32, height: u32| {
+            device
+                .create_texture(&TextureDescriptor {
+                    label: Some(label),
+                    size: Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: GI_FORMAT,
+                    usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&Default::default())
+        };
+
+        // Whole cascade 0 probes across the scene
+        let probes = |size: u32| {
+            ((size as f32 * settings.scale).ceil() as u32)
+                .div_ceil(PROBE_SPACING)
+                .max(1)
+        };
+        let (probes_x, probes_y) = (probes(gbuffer.size.0), probes(gbuffer.size.1));
+
+        let (scene_x, scene_y) = (probes_x * PROBE_SPACING, probes_y * PROBE_SPACING);
+        let scene = texture("GI Scene", scene_x, scene_y);
+        // Cascades starting past the scene's diagonal would only trace outside of it, and
+        // enough of them overflow the shifts sizing them
+        let diagonal = (scene_x as f32).hypot(scene_y as f32);
+        let reaching = (3.0 * diagonal / PROBE_SPACING as f32 + 1.0)
+            .log(4.0)
+            .ceil() as u32;
+        let count = settings.cascades.clamp(1, reaching.max(1));
+
+        // Half the probes per axis each cascade up, with a block of directions twice as wide
+        let cascades = (0..count)
+            .map(|i| {
+                let block = 2 << i;
+                texture(
+                    &format!("GI Cascade {}", i),
+                    probes_x.div_ceil(1 << i) * block,
+                    probes_y.div_ceil(1 << i) * block,
+                )
+            })
+            .collect();
+        let irradiance = texture("GI Irradiance", probes_x, probes_y);
+        (scene, cascades, irradiance)
+    }
+
+    // Light reaching each cascade 0 probe, covering the whole G-buffer
+    pub fn view(&self) -> &TextureView {
+        &self.irradiance_view
+    }
+
+    // Needs every layer to be lit
+    pub fn execute(&self, data: &mut RenderPassData, layers: &[LayerLighting]) {
+        let occluders = occluder_mask(layers);
+        let lit = layers
+            .iter()
+            .enumerate()
+            .filter(|(_, layer)| layer.is_lit())
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+
+        // Inputs a dispatch does not read still need a texture other than its target
+        let bind_group = |cascade: u32, input: &TextureView, upper: &TextureView, target| {
+            let params_b = data.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("GI Params B"),
+                contents: bytemuck::bytes_of(&ParamsUniform {
+                    occluders,
+                    lit,
+                    cascade,
+                    cascade_count: self.cascade_views.len() as u32,
+                    bounce: self.settings.bounce,
+                    _pad: [0; 3],
+                }),
+                usage: BufferUsages::UNIFORM,
+            });
+            data.device.create_bind_group(&BindGroupDescriptor {
+                label: Some("GI BG"),
+                layout: &self.bgl,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&data.gbuffer.color_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&data.gbuffer.emissive_view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&data.gbuffer.light_view),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(input),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::TextureView(upper),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: BindingResource::TextureView(target),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: params_b.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        let scene_bg = bind_group(
+            0,
+            &self.irradiance_view,
+            &self.irradiance_view,
+            &self.scene_view,
+        );
+        // Top cascade first, each merging the one above it
+        let cascade_bgs: Vec<(BindGroup, &TextureView)> = (0..self.cascade_views.len() as u32)
+            .rev()
+            .map(|i| {
+                let target = &self.cascade_views[i as usize];
+                let upper = self
+                    .cascade_views
+                    .get(i as usize + 1)
+                    .unwrap_or(&self.irradiance_view);
+                (bind_group(i, &self.scene_view, upper, target), target)
+            })
+            .collect();
+        let integrate_bg = bind_group(
+            0,
+            &self.scene_view,
+            &self.cascade_views[0],
+            &self.irradiance_view,
+        );
+
+        let workgroups = |view: &TextureView| {
+            let size = view.texture().size();
+            (
+                size.width.div_ceil(WORKGROUP_SIZE),
+                size.height.div_ceil(WORKGROUP_SIZE),
+            )
+        };
+        let mut cpass = data.encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("GI Pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&self.scene_pipeline);
+        cpass.set_bind_group(0, &scene_bg, &[]);
+        let (x, y) = workgroups(&self.scene_view);
+        cpass.dispatch_workgroups(x, y, 1);
+        cpass.set_pipeline(&self.cascade_pipeline);
+        for (cascade_bg, target) in &cascade_bgs {
+            cpass.set_bind_group(0, cascade_bg, &[]);
+            let (x, y) = workgroups(target);
+            cpass.dispatch_workgroups(x, y, 1);
+        }
+        cpass.set_pipeline(&self.integrate_pipeline);
+        cpass.set_bind_group(0, &integrate_bg, &[]);
+        let (x, y) = workgroups(&self.irradiance_view);
+        cpass.dispatch_workgroups(x, y, 1);
+    }
+}
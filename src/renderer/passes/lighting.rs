@@ -0,0 +1,511 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor,
+    BlendOperation, BlendState, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Color,
+    ColorTargetState, ColorWrites, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, FilterMode, FragmentState, LoadOp, Operations,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, StoreOp, TextureSampleType, TextureView, TextureViewDimension,
+    VertexState,
+};
+
+use crate::renderer::{
+    camera::Camera,
+    gbuffer::GBuffer,
+    light::{lights_raw, occluder_mask, LayerLighting, Light},
+};
+
+use super::{create_shader, RenderPassData, LIGHT_SOURCE};
+
+// Pixels per side of the screen tiles lights are binned into, and how many lights a tile
+// holds before dropping the rest. Both match lighting.wgsl and light_culling.wgsl.
+const TILE_SIZE: u32 = 16;
+const MAX_TILE_LIGHTS: u32 = 256;
+
+// Lights add up in the light target
+const ADDITIVE: BlendComponent = BlendComponent {
+    src_factor: BlendFactor::One,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+// Layer being lit, matches `Layer` in lighting.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniform {
+    index: u32,
+    mask: u32,
+    shadowed: u32,
+    _pad: u32,
+}
+
+// Matches `Params` in light_culling.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullingUniform {
+    size: [u32; 2],
+    tiles: [u32; 2],
+}
+
+// Light count and light indices of every tile
+struct Tiles {
+    counts_b: Buffer,
+    lights_b: Buffer,
+    size: (u32, u32),
+}
+
+impl Tiles {
+    fn new(device: &Device, gbuffer: &GBuffer) -> Self {
+        let size = (
+            gbuffer.size.0.div_ceil(TILE_SIZE),
+            gbuffer.size.1.div_ceil(TILE_SIZE),
+        );
+        let count = (size.0 * size.1) as u64;
+        let buffer = |label, len: u64| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: len * 4,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            counts_b: buffer("Lighting Tile Counts B", count),
+            lights_b: buffer("Lighting Tile Lights B", count * MAX_TILE_LIGHTS as u64),
+            size,
+        }
+    }
+}
+
+// Accumulates the light reaching each finished layer into its light target, starting
+// from the layer's ambient light. Lights are binned into screen tiles once per frame so
+// each pixel only goes through the lights of its tile, unless created without culling.
+// Unlit layers are skipped, the composite ignores their light target.
+pub struct Lighting {
+    pipeline: RenderPipeline,
+    culling_pipeline: ComputePipeline,
+    camera_bg: BindGroup,
+    camera_b: Buffer,
+    lights_bgl: BindGroupLayout,
+    cookie_sampler: Sampler,
+    culling_bgl: BindGroupLayout,
+    layer_bgl: BindGroupLayout,
+    layer_bs: Vec<Buffer>,
+    tiles: Tiles,
+    culling: bool,
+    // Uploaded and binned once per frame by `prepare`, None without lights
+    lights_bg: Option<BindGroup>,
+    layers: Vec<LayerLighting>,
+}
+
+impl Lighting {
+    // Without `culling` every pixel goes through every light, only worth it for a few
+    pub fn new(device: &Device, gbuffer: &GBuffer, culling: bool) -> Self {
+        let shader = create_shader(
+            device,
+            "Lighting Shader",
+            LIGHT_SOURCE,
+            include_str!("../../shaders/lighting.wgsl"),
+        );
+        let culling_shader = create_shader(
+            device,
+            "Light Culling Shader",
+            LIGHT_SOURCE,
+            include_str!("../../shaders/light_culling.wgsl"),
+        );
+
+        let camera = Camera::default();
+        let camera_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Lighting Camera B"),
+            contents: bytemuck::cast_slice(&[camera]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let camera_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Lighting Camera BGL"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let camera_bg = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Lighting Camera BG"),
+            layout: &camera_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_b.as_entire_binding(),
+            }],
+        });
+
+        let storage = |binding, visibility, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let lights_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Lighting Lights BGL"),
+            entries: &[
+                storage(0, ShaderStages::FRAGMENT, true),
+                storage(1, ShaderStages::FRAGMENT, true),
+                storage(2, ShaderStages::FRAGMENT, true),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let cookie_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Lighting Cookie Sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let culling_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Light Culling BGL"),
+            entries: &[
+                storage(0, ShaderStages::COMPUTE, true),
+                storage(1, ShaderStages::COMPUTE, false),
+                storage(2, ShaderStages::COMPUTE, false),
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // The whole normal array is bound with the layer's index, GL cannot sample views
+        // of single array layers. The shadow map and SDF are shared by all layers.
+        let layer_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Lighting Layer BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layer_bs = (0..gbuffer.layers)
+            .map(|layer| {
+                device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some(&format!("Lighting Layer {} B", layer)),
+                    contents: bytemuck::bytes_of(&LayerUniform {
+                        index: layer,
+                        mask: !0,
+                        shadowed: 1,
+                        _pad: 0,
+                    }),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Lighting Pipeline Layout"),
+            bind_group_layouts: &[&camera_bgl, &lights_bgl, &layer_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let constants = [("CULLING", culling as u32 as f64)];
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Lighting Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format: gbuffer.light_format,
+                    blend: Some(BlendState {
+                        color: ADDITIVE,
+                        alpha: ADDITIVE,
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let culling_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Light Culling Pipeline Layout"),
+            bind_group_layouts: &[&camera_bgl, &culling_bgl],
+            push_constant_ranges: &[],
+        });
+        let culling_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Light Culling Pipeline"),
+            layout: Some(&culling_pipeline_layout),
+            module: &culling_shader,
+            entry_point: Some("cull"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            culling_pipeline,
+            camera_bg,
+            camera_b,
+            lights_bgl,
+            cookie_sampler,
+            culling_bgl,
+            layer_bgl,
+            layer_bs,
+            tiles: Tiles::new(device, gbuffer),
+            culling,
+            lights_bg: None,
+            layers: vec![LayerLighting::default(); gbuffer.layers as usize],
+        }
+    }
+
+    // The tiles follow the G-buffer's size, the pipelines are kept
+    pub fn resize(&mut self, device: &Device, gbuffer: &GBuffer) {
+        self.tiles = Tiles::new(device, gbuffer);
+    }
+
+    // Uploads the frame's lights, shared by every layer, bins them into tiles with the
+    // camera's view and sets how each layer is lit
+    pub fn prepare(
+        &mut self,
+        data: &mut RenderPassData,
+        lights: &[Light],
+        layers: &[LayerLighting],
+        camera: &Camera,
+    ) {
+        data.queue
+            .write_buffer(&self.camera_b, 0, bytemuck::cast_slice(&[*camera]));
+
+        // Nothing casts shadows without occluder layers
+        let occluders = occluder_mask(layers);
+        for (index, (layer_b, layer)) in self.layer_bs.iter().zip(layers).enumerate() {
+            let uniform = LayerUniform {
+                index: index as u32,
+                mask: layer.mask,
+                // Occluders would shadow themselves past their first pixel
+                shadowed: (occluders != 0 && !layer.occluder) as u32,
+                _pad: 0,
+            };
+            data.queue
+                .write_buffer(layer_b, 0, bytemuck::bytes_of(&uniform));
+        }
+        self.layers = layers.to_vec();
+
+        self.lights_bg = None;
+        if lights.is_empty() {
+            return;
+        }
+        let raw = lights_raw(lights);
+        let lights_b = data.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Lighting Lights B"),
+            contents: bytemuck::cast_slice(&raw),
+            usage: BufferUsages::STORAGE,
+        });
+        let culling_b = data.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Light Culling Params B"),
+            contents: bytemuck::bytes_of(&CullingUniform {
+                size: [data.gbuffer.size.0, data.gbuffer.size.1],
+                tiles: [self.tiles.size.0, self.tiles.size.1],
+            }),
+            usage: BufferUsages::UNIFORM,
+        });
+        let culling_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Light Culling BG"),
+            layout: &self.culling_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: lights_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.tiles.counts_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: self.tiles.lights_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: culling_b.as_entire_binding(),
+                },
+            ],
+        });
+        self.lights_bg = Some(data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Lighting Lights BG"),
+            layout: &self.lights_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: lights_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.tiles.counts_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: self.tiles.lights_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(data.cookies.view()),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::Sampler(&self.cookie_sampler),
+                },
+            ],
+        }));
+
+        if !self.culling {
+            return;
+        }
+        // A workgroup per tile
+        let mut cpass = data.encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Light Culling Pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&self.culling_pipeline);
+        cpass.set_bind_group(0, &self.camera_bg, &[]);
+        cpass.set_bind_group(1, &culling_bg, &[]);
+        cpass.dispatch_workgroups(self.tiles.size.0, self.tiles.size.1, 1);
+    }
+
+    // `shadow_map` comes from the shadow pass run on this frame's lights
+    pub fn execute(&self, data: &mut RenderPassData, layer: u32, shadow_map: &TextureView) {
+        let lighting = &self.layers[layer as usize];
+        if !lighting.is_lit() {
+            return;
+        }
+        let ambient = lighting.ambient_linear().map(|c| c as f64);
+
+        let views = data.gbuffer.layer_views(layer);
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("Lighting Pass Layer {}", layer)),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &views.light,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color {
+                        r: ambient[0],
+                        g: ambient[1],
+                        b: ambient[2],
+                        a: 1.0,
+                    }),
+                    store: StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+
+        let Some(lights_bg) = &self.lights_bg else {
+            return;
+        };
+        let layer_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Lighting Layer BG"),
+            layout: &self.layer_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&data.gbuffer.normal_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.layer_bs[layer as usize].as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(shadow_map),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(&data.gbuffer.sdf_view),
+                },
+            ],
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.camera_bg, &[]);
+        rpass.set_bind_group(1, lights_bg, &[]);
+        rpass.set_bind_group(2, &layer_bg, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
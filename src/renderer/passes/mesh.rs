@@ -0,0 +1,336 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBinding,
+    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, CompareFunction,
+    DepthStencilState, Device, Extent3d, FragmentState, IndexFormat, PipelineLayoutDescriptor,
+    Queue, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler,
+    SamplerBindingType, SamplerDescriptor, ShaderStages, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension,
+    VertexState,
+};
+
+use crate::renderer::{
+    camera::Camera,
+    gbuffer::GBuffer,
+    mesh::{MeshDraw, TransformRaw, Vertex},
+};
+
+use super::{create_shader, RenderPassData, GBUFFER_OUT_SOURCE};
+
+pub struct Mesh {
+    pipeline: RenderPipeline,
+    camera_bg: BindGroup,
+    camera_b: Buffer,
+    // Every draw's transform for the frame, each bound at its own dynamic offset
+    transforms_bgl: BindGroupLayout,
+    transforms_bg: BindGroup,
+    transforms_b: Buffer,
+    capacity: u32,
+    stride: u32,
+    // Index of each layer's first transform, filled by `prepare`
+    layer_starts: Vec<u32>,
+    texture_bgl: BindGroupLayout,
+    sampler: Sampler,
+    white_bg: BindGroup,
+    // Created the first time a texture is drawn, user textures never change
+    texture_bgs: Vec<Option<BindGroup>>,
+}
+
+impl Mesh {
+    pub fn new(device: &Device, queue: &Queue, gbuffer: &GBuffer) -> Self {
+        let shader = create_shader(
+            device,
+            "Mesh Shader",
+            GBUFFER_OUT_SOURCE,
+            include_str!("../../shaders/mesh.wgsl"),
+        );
+
+        let camera = Camera::default();
+        let camera_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Mesh Camera B"),
+            contents: bytemuck::cast_slice(&[camera]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let camera_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Mesh Camera BGL"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let camera_bg = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Mesh Camera BG"),
+            layout: &camera_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_b.as_entire_binding(),
+            }],
+        });
+
+        let transforms_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Mesh Transforms BGL"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: BufferSize::new(size_of::<TransformRaw>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let stride = (size_of::<TransformRaw>() as u32)
+            .next_multiple_of(device.limits().min_uniform_buffer_offset_alignment);
+        let capacity = 1;
+        let transforms_b = Self::create_transforms(device, stride, capacity);
+        let transforms_bg = Self::create_transforms_bg(device, &transforms_bgl, &transforms_b);
+
+        let texture_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Mesh Texture BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Mesh Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Bound for untextured draws so the shader can always sample
+        let white = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("Mesh White Texture"),
+                size: Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &[255, 255, 255, 255],
+        );
+        let white_bg = Self::create_texture_bg(
+            device,
+            &texture_bgl,
+            &sampler,
+            &white.create_view(&Default::default()),
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Mesh Pipeline Layout"),
+            bind_group_layouts: &[&camera_bgl, &transforms_bgl, &texture_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Mesh Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[Vertex::layout()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &gbuffer.color_targets(BlendState::ALPHA_BLENDING, false),
+                compilation_options: Default::default(),
+            }),
+            primitive: Default::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: gbuffer.depth_format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: gbuffer.multisample(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            camera_bg,
+            camera_b,
+            transforms_bgl,
+            transforms_bg,
+            transforms_b,
+            capacity,
+            stride,
+            layer_starts: Vec::new(),
+            texture_bgl,
+            sampler,
+            white_bg,
+            texture_bgs: Vec::new(),
+        }
+    }
+
+    fn create_transforms(device: &Device, stride: u32, capacity: u32) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("Mesh Transforms B"),
+            size: stride as u64 * capacity as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_transforms_bg(
+        device: &Device,
+        layout: &BindGroupLayout,
+        buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Mesh Transforms BG"),
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: BufferSize::new(size_of::<TransformRaw>() as u64),
+                }),
+            }],
+        })
+    }
+
+    fn create_texture_bg(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        view: &TextureView,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Mesh Texture BG"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::Sampler(sampler),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(view),
+                },
+            ],
+        })
+    }
+
+    // Uploads the transforms of every layer's draws at once, the layers are recorded into
+    // the same submission so they can't share a region of the buffer
+    pub fn prepare(&mut self, data: &mut RenderPassData, draws: &[Vec<MeshDraw>], camera: &Camera) {
+        data.queue
+            .write_buffer(&self.camera_b, 0, bytemuck::cast_slice(&[*camera]));
+
+        self.layer_starts.clear();
+        let mut count = 0;
+        for layer_draws in draws {
+            self.layer_starts.push(count);
+            count += layer_draws.len() as u32;
+        }
+        if count == 0 {
+            return;
+        }
+
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            self.transforms_b = Self::create_transforms(data.device, self.stride, self.capacity);
+            self.transforms_bg =
+                Self::create_transforms_bg(data.device, &self.transforms_bgl, &self.transforms_b);
+        }
+
+        let stride = self.stride as usize;
+        let mut bytes = vec![0; count as usize * stride];
+        for (draw, chunk) in draws.iter().flatten().zip(bytes.chunks_exact_mut(stride)) {
+            let raw = TransformRaw::from(&draw.transform);
+            chunk[..size_of::<TransformRaw>()].copy_from_slice(bytemuck::bytes_of(&raw));
+
+            if let Some(texture) = draw.texture {
+                let index = texture.0 as usize;
+                if index >= self.texture_bgs.len() {
+                    self.texture_bgs.resize(index + 1, None);
+                }
+                self.texture_bgs[index].get_or_insert_with(|| {
+                    Self::create_texture_bg(
+                        data.device,
+                        &self.texture_bgl,
+                        &self.sampler,
+                        data.textures.view(texture),
+                    )
+                });
+            }
+        }
+        data.queue.write_buffer(&self.transforms_b, 0, &bytes);
+    }
+
+    pub fn execute(&self, data: &mut RenderPassData, draws: &[MeshDraw], layer: u32) {
+        if draws.is_empty() {
+            return;
+        }
+
+        let views = data.gbuffer.layer_views(layer);
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("Mesh Pass Layer {}", layer)),
+            color_attachments: &views.color_attachments(),
+            depth_stencil_attachment: Some(views.depth_attachment()),
+            ..Default::default()
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.camera_bg, &[]);
+
+        let start = self.layer_starts[layer as usize];
+        for (index, draw) in (start..).zip(draws) {
+            let mesh = data.meshes.get(draw.mesh);
+            if mesh.index_count == 0 {
+                continue;
+            }
+
+            let texture_bg = match draw.texture {
+                Some(texture) => self.texture_bgs[texture.0 as usize]
+                    .as_ref()
+                    .expect("texture bind groups are created by prepare"),
+                None => &self.white_bg,
+            };
+            rpass.set_bind_group(1, &self.transforms_bg, &[index * self.stride]);
+            rpass.set_bind_group(2, texture_bg, &[]);
+            rpass.set_vertex_buffer(0, mesh.vertex_b.slice(..));
+            rpass.set_index_buffer(mesh.index_b.slice(..), IndexFormat::Uint32);
+            rpass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+    }
+}
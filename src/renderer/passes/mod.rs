@@ -1,16 +1,58 @@
+mod ao;
+mod bloom;
 mod composite;
 mod geometry;
+mod gi;
+mod lighting;
+mod mesh;
+mod particles;
+mod resolve;
+mod sdf;
+mod shadows;
+mod tilemap;
 
-pub use composite::{Composite, CompositeMode};
+pub use ao::{Ao, AoSettings};
+pub use bloom::{Bloom, BloomSettings, BloomSource};
+pub use composite::{Composite, CompositeMode, Upscale};
 pub use geometry::Geometry;
+pub use gi::{Gi, GiSettings};
+pub use lighting::Lighting;
+pub use mesh::Mesh;
+pub use particles::Particles;
+pub use resolve::Resolve;
+pub use sdf::Sdf;
+pub use shadows::Shadows;
+pub use tilemap::Tilemap;
 
-use wgpu::{CommandEncoder, Device, Queue};
+use wgpu::{CommandEncoder, Device, Queue, ShaderModule, ShaderModuleDescriptor, ShaderSource};
 
-use super::gbuffer::GBuffer;
+use super::{
+    cookie::Cookies, gbuffer::GBuffer, mesh::Meshes, particles::Emitters, texture::Textures,
+    tilemap::Tilemaps,
+};
 
 pub struct RenderPassData<'a> {
     pub gbuffer: &'a GBuffer,
     pub encoder: &'a mut CommandEncoder,
     pub device: &'a Device,
     pub queue: &'a Queue,
+    pub textures: &'a Textures,
+    pub meshes: &'a Meshes,
+    pub tilemaps: &'a Tilemaps,
+    pub emitters: &'a Emitters,
+    pub cookies: &'a Cookies,
+}
+
+// Definitions of the G-buffer targets shared by the passes drawing into a layer
+const GBUFFER_OUT_SOURCE: &str = include_str!("../../shaders/gbuffer_out.wgsl");
+// Layout of a light shared by the passes reading the frame's lights
+const LIGHT_SOURCE: &str = include_str!("../../shaders/light.wgsl");
+
+// Compiles `source` with WGSL shared between shaders in front of it, the way materials are
+// appended to geometry.wgsl
+fn create_shader(device: &Device, label: &str, shared: &str, source: &str) -> ShaderModule {
+    device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(format!("{}\n{}", shared, source).into()),
+    })
 }
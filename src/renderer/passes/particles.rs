@@ -0,0 +1,253 @@
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsages,
+    CompareFunction, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
+    DepthStencilState, Device, FragmentState, PipelineLayoutDescriptor, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderStages, VertexState,
+};
+
+use crate::renderer::{camera::Camera, gbuffer::GBuffer, particles::WORKGROUP_SIZE};
+
+use super::{create_shader, RenderPassData, GBUFFER_OUT_SOURCE};
+
+pub struct Particles {
+    simulate_pipeline: ComputePipeline,
+    simulate_bgl: BindGroupLayout,
+    draw_pipeline: RenderPipeline,
+    draw_bgl: BindGroupLayout,
+    camera_bg: BindGroup,
+    camera_b: Buffer,
+}
+
+impl Particles {
+    pub fn new(device: &Device, gbuffer: &GBuffer) -> Self {
+        let simulate_shader =
+            device.create_shader_module(include_wgsl!("../../shaders/particles_simulate.wgsl"));
+        let draw_shader = create_shader(
+            device,
+            "Particles Shader",
+            GBUFFER_OUT_SOURCE,
+            include_str!("../../shaders/particles.wgsl"),
+        );
+
+        let simulate_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Particles Simulate BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let simulate_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Particles Simulate Pipeline Layout"),
+            bind_group_layouts: &[&simulate_bgl],
+            push_constant_ranges: &[],
+        });
+        let simulate_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Particles Simulate Pipeline"),
+            layout: Some(&simulate_pipeline_layout),
+            module: &simulate_shader,
+            entry_point: Some("simulate"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let camera = Camera::default();
+        let camera_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particles Camera B"),
+            contents: bytemuck::cast_slice(&[camera]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let camera_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Particles Camera BGL"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let camera_bg = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Particles Camera BG"),
+            layout: &camera_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_b.as_entire_binding(),
+            }],
+        });
+
+        let draw_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Particles Draw BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let draw_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Particles Draw Pipeline Layout"),
+            bind_group_layouts: &[&camera_bgl, &draw_bgl],
+            push_constant_ranges: &[],
+        });
+        let draw_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Particles Draw Pipeline"),
+            layout: Some(&draw_pipeline_layout),
+            vertex: VertexState {
+                module: &draw_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &draw_shader,
+                entry_point: Some("fs_main"),
+                targets: &gbuffer.color_targets(BlendState::ALPHA_BLENDING, false),
+                compilation_options: Default::default(),
+            }),
+            primitive: Default::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: gbuffer.depth_format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: gbuffer.multisample(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            simulate_pipeline,
+            simulate_bgl,
+            draw_pipeline,
+            draw_bgl,
+            camera_bg,
+            camera_b,
+        }
+    }
+
+    // Steps every emitter once, must run before the layers are drawn
+    pub fn simulate(&self, data: &mut RenderPassData) {
+        let simulate_bgs: Vec<(BindGroup, u32)> = data
+            .emitters
+            .iter()
+            .map(|emitter| {
+                let bind_group = data.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Particles Simulate BG"),
+                    layout: &self.simulate_bgl,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: emitter.params_b.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: emitter.particles_b.as_entire_binding(),
+                        },
+                    ],
+                });
+                (bind_group, emitter.capacity.div_ceil(WORKGROUP_SIZE))
+            })
+            .collect();
+        if simulate_bgs.is_empty() {
+            return;
+        }
+
+        let mut cpass = data.encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Particles Simulate Pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&self.simulate_pipeline);
+        for (bind_group, workgroups) in &simulate_bgs {
+            cpass.set_bind_group(0, bind_group, &[]);
+            cpass.dispatch_workgroups(*workgroups, 1, 1);
+        }
+    }
+
+    pub fn execute(&self, data: &mut RenderPassData, layer: u32, camera: &Camera) {
+        let draw_bgs: Vec<(BindGroup, u32)> = data
+            .emitters
+            .iter()
+            .filter(|emitter| emitter.layer == layer)
+            .map(|emitter| {
+                let bind_group = data.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Particles Draw BG"),
+                    layout: &self.draw_bgl,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: emitter.params_b.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: emitter.particles_b.as_entire_binding(),
+                        },
+                    ],
+                });
+                (bind_group, emitter.capacity)
+            })
+            .collect();
+        if draw_bgs.is_empty() {
+            return;
+        }
+
+        data.queue
+            .write_buffer(&self.camera_b, 0, bytemuck::cast_slice(&[*camera]));
+
+        let views = data.gbuffer.layer_views(layer);
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("Particles Pass Layer {}", layer)),
+            color_attachments: &views.color_attachments(),
+            depth_stencil_attachment: Some(views.depth_attachment()),
+            ..Default::default()
+        });
+
+        rpass.set_pipeline(&self.draw_pipeline);
+        rpass.set_bind_group(0, &self.camera_bg, &[]);
+        for (bind_group, capacity) in &draw_bgs {
+            rpass.set_bind_group(1, bind_group, &[]);
+            rpass.draw(0..6, 0..*capacity);
+        }
+    }
+}
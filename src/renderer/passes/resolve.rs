@@ -0,0 +1,145 @@
+use wgpu::{
+    include_wgsl, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, CompareFunction,
+    DepthStencilState, Device, FragmentState, LoadOp, Operations, PipelineCompilationOptions,
+    PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp,
+    TextureSampleType, TextureViewDimension, VertexState,
+};
+
+use crate::renderer::gbuffer::GBuffer;
+
+use super::RenderPassData;
+
+// Finishes a multisampled layer by resolving the shared attachments into the G-buffer
+// arrays. Done in a shader rather than with resolve targets so it happens once per layer
+// and covers ids and depth, which the hardware cannot resolve.
+pub struct Resolve {
+    pipeline: RenderPipeline,
+    msaa_bgl: BindGroupLayout,
+}
+
+impl Resolve {
+    pub fn new(device: &Device, gbuffer: &GBuffer) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("../../shaders/resolve.wgsl"));
+
+        let texture_entry = |binding, sample_type| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                multisampled: true,
+                view_dimension: TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let float = TextureSampleType::Float { filterable: false };
+        let msaa_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Resolve MSAA BGL"),
+            entries: &[
+                texture_entry(0, float),
+                texture_entry(1, float),
+                texture_entry(2, float),
+                texture_entry(3, TextureSampleType::Uint),
+                texture_entry(4, float),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Resolve Pipeline Layout"),
+            bind_group_layouts: &[&msaa_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let sample_count = [("SAMPLE_COUNT", gbuffer.sample_count as f64)];
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Resolve Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &gbuffer.color_targets(BlendState::REPLACE, true),
+                compilation_options: PipelineCompilationOptions {
+                    constants: &sample_count,
+                    ..Default::default()
+                },
+            }),
+            primitive: Default::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: gbuffer.depth_format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Always,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self { pipeline, msaa_bgl }
+    }
+
+    pub fn execute(&self, data: &mut RenderPassData, layer: u32) {
+        let Some(msaa) = &data.gbuffer.msaa else {
+            return;
+        };
+
+        let entries = [
+            &msaa.color,
+            &msaa.normal,
+            &msaa.emissive,
+            &msaa.id,
+            &msaa.depth,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, view)| BindGroupEntry {
+            binding: binding as u32,
+            resource: BindingResource::TextureView(view),
+        })
+        .collect::<Vec<_>>();
+        let msaa_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Resolve MSAA BG"),
+            layout: &self.msaa_bgl,
+            entries: &entries,
+        });
+
+        let views = data.gbuffer.layer_views(layer);
+        let color_attachments =
+            [&views.color, &views.normal, &views.emissive, &views.id].map(|view| {
+                Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })
+            });
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("Resolve Pass Layer {}", layer)),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: &views.depth,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            ..Default::default()
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &msaa_bg, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,233 @@
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, Extent3d,
+    PipelineLayoutDescriptor, ShaderStages, StorageTextureAccess, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDimension,
+};
+
+use crate::renderer::{
+    gbuffer::GBuffer,
+    light::{occluder_mask, LayerLighting},
+};
+
+use super::RenderPassData;
+
+const WORKGROUP_SIZE: u32 = 8;
+// Pixel coordinates need more than f16
+const SEEDS_FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+
+// Matches `Params` in sdf.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParamsUniform {
+    occluders: u32,
+    step: i32,
+    _pad: [u32; 2],
+}
+
+// Builds the G-buffer's signed distance field from the alpha of the occluder layers by
+// jump flooding between two seed textures. Without occluder layers every pixel is far
+// from everything.
+pub struct Sdf {
+    seed_pipeline: ComputePipeline,
+    jump_pipeline: ComputePipeline,
+    resolve_pipeline: ComputePipeline,
+    bgl: BindGroupLayout,
+    seed_views: [TextureView; 2],
+}
+
+impl Sdf {
+    pub fn new(device: &Device, gbuffer: &GBuffer) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("../../shaders/sdf.wgsl"));
+
+        let bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("SDF BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: SEEDS_FORMAT,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: gbuffer.sdf_format,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("SDF Pipeline Layout"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(&format!("SDF {} Pipeline", entry_point)),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            seed_pipeline: pipeline("seed"),
+            jump_pipeline: pipeline("jump"),
+            resolve_pipeline: pipeline("resolve"),
+            bgl,
+            seed_views: Self::create_seeds(device, gbuffer),
+        }
+    }
+
+    // The seeds follow the G-buffer's size, the pipelines are kept
+    pub fn resize(&mut self, device: &Device, gbuffer: &GBuffer) {
+        self.seed_views = Self::create_seeds(device, gbuffer);
+    }
+
+    fn create_seeds(device: &Device, gbuffer: &GBuffer) -> [TextureView; 2] {
+        ["A", "B"].map(|name| {
+            device
+                .create_texture(&TextureDescriptor {
+                    label: Some(&format!("SDF Seeds {}", name)),
+                    size: Extent3d {
+                        width: gbuffer.size.0,
+                        height: gbuffer.size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: SEEDS_FORMAT,
+                    usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&Default::default())
+        })
+    }
+
+    // Needs every occluder layer to be finished
+    pub fn execute(&self, data: &mut RenderPassData, layers: &[LayerLighting]) {
+        let occluders = occluder_mask(layers);
+
+        // Halving from the largest power of two below the size down to single pixels
+        let (width, height) = data.gbuffer.size;
+        let first = (width.max(height).next_power_of_two() / 2).max(1) as i32;
+        let steps: Vec<i32> = match occluders {
+            0 => Vec::new(),
+            _ => {
+                std::iter::successors(Some(first), |step| (*step > 1).then_some(step / 2)).collect()
+            }
+        };
+
+        // Every dispatch reads the seeds the previous one wrote
+        let bind_group = |step: i32, read: usize| {
+            let params_b = data.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("SDF Params B"),
+                contents: bytemuck::bytes_of(&ParamsUniform {
+                    occluders,
+                    step,
+                    _pad: [0; 2],
+                }),
+                usage: BufferUsages::UNIFORM,
+            });
+            data.device.create_bind_group(&BindGroupDescriptor {
+                label: Some("SDF BG"),
+                layout: &self.bgl,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&data.gbuffer.color_view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&self.seed_views[read]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&self.seed_views[1 - read]),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(&data.gbuffer.sdf_view),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: params_b.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let seed_bg = bind_group(0, 1);
+        let jump_bgs: Vec<BindGroup> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| bind_group(*step, i % 2))
+            .collect();
+        let resolve_bg = bind_group(0, steps.len() % 2);
+
+        let workgroups = (
+            width.div_ceil(WORKGROUP_SIZE),
+            height.div_ceil(WORKGROUP_SIZE),
+        );
+        let mut cpass = data.encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("SDF Pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&self.seed_pipeline);
+        cpass.set_bind_group(0, &seed_bg, &[]);
+        cpass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        cpass.set_pipeline(&self.jump_pipeline);
+        for jump_bg in &jump_bgs {
+            cpass.set_bind_group(0, jump_bg, &[]);
+            cpass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+        cpass.set_pipeline(&self.resolve_pipeline);
+        cpass.set_bind_group(0, &resolve_bg, &[]);
+        cpass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+    }
+}
@@ -0,0 +1,272 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    Color, ColorTargetState, ColorWrites, Device, Extent3d, FragmentState, LoadOp, Operations,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDimension, VertexState,
+};
+
+use crate::renderer::{
+    camera::Camera,
+    light::{casts_hard_shadow, occluder_mask, LayerLighting, Light, LightRaw},
+};
+
+use super::{create_shader, RenderPassData, LIGHT_SOURCE};
+
+const SHADOW_MAP_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+// Matches `Params` in shadows.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParamsUniform {
+    occluders: u32,
+    _pad: [u32; 3],
+}
+
+// Renders a polar shadow map row for every light by marching rays through the occluder
+// layers, read back by the lighting pass. Without occluder layers the map is only cleared,
+// which leaves every light unshadowed.
+pub struct Shadows {
+    pipeline: RenderPipeline,
+    camera_bg: BindGroup,
+    camera_b: Buffer,
+    lights_bgl: BindGroupLayout,
+    resolution: u32,
+    // Grown to fit the frame's lights, one row each
+    view: TextureView,
+    capacity: u32,
+}
+
+impl Shadows {
+    // `resolution` is the number of directions around each light
+    pub fn new(device: &Device, resolution: u32) -> Self {
+        let shader = create_shader(
+            device,
+            "Shadows Shader",
+            LIGHT_SOURCE,
+            include_str!("../../shaders/shadows.wgsl"),
+        );
+
+        let camera = Camera::default();
+        let camera_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Shadows Camera B"),
+            contents: bytemuck::cast_slice(&[camera]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let camera_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadows Camera BGL"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let camera_bg = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Shadows Camera BG"),
+            layout: &camera_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: camera_b.as_entire_binding(),
+            }],
+        });
+
+        let lights_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Shadows Lights BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Shadows Pipeline Layout"),
+            bind_group_layouts: &[&camera_bgl, &lights_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let constants = [("RESOLUTION", resolution as f64)];
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Shadows Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(ColorTargetState {
+                    format: SHADOW_MAP_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let capacity = 1;
+        Self {
+            pipeline,
+            camera_bg,
+            camera_b,
+            lights_bgl,
+            resolution,
+            view: Self::create_map(device, resolution, capacity),
+            capacity,
+        }
+    }
+
+    fn create_map(device: &Device, resolution: u32, capacity: u32) -> TextureView {
+        device
+            .create_texture(&TextureDescriptor {
+                label: Some("Shadow Map"),
+                size: Extent3d {
+                    width: resolution,
+                    height: capacity,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: SHADOW_MAP_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    }
+
+    // Row `i` belongs to the `i`th light drawn this frame casting hard shadows
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    // Needs every occluder layer to be finished
+    pub fn execute(
+        &mut self,
+        data: &mut RenderPassData,
+        lights: &[Light],
+        layers: &[LayerLighting],
+        camera: &Camera,
+    ) {
+        let occluders = occluder_mask(layers);
+        // Rows of the lights casting hard shadows, in the order `lights_raw` numbers them
+        let hard: Vec<LightRaw> = lights
+            .iter()
+            .filter(|light| casts_hard_shadow(light))
+            .map(LightRaw::from)
+            .collect();
+        // Without occluders the map is never read, it only grows for lights that need it.
+        // Lights past the largest texture the device allows go unshadowed.
+        let max = data.device.limits().max_texture_dimension_2d;
+        let count = match occluders {
+            0 => 0,
+            _ => (hard.len() as u32).min(max),
+        };
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two().min(max);
+            self.view = Self::create_map(data.device, self.resolution, self.capacity);
+        }
+
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Shadows Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::WHITE),
+                    store: StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            ..Default::default()
+        });
+        if hard.is_empty() || occluders == 0 {
+            return;
+        }
+
+        data.queue
+            .write_buffer(&self.camera_b, 0, bytemuck::cast_slice(&[*camera]));
+        let lights_b = data.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Shadows Lights B"),
+            contents: bytemuck::cast_slice(&hard),
+            usage: BufferUsages::STORAGE,
+        });
+        let params_b = data.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Shadows Params B"),
+            contents: bytemuck::bytes_of(&ParamsUniform {
+                occluders,
+                _pad: [0; 3],
+            }),
+            usage: BufferUsages::UNIFORM,
+        });
+        let lights_bg = data.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Shadows Lights BG"),
+            layout: &self.lights_bgl,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: lights_b.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&data.gbuffer.color_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: params_b.as_entire_binding(),
+                },
+            ],
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.camera_bg, &[]);
+        rpass.set_bind_group(1, &lights_bg, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,244 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBindingType,
+    BufferUsages, CompareFunction, DepthStencilState, Device, FragmentState,
+    PipelineLayoutDescriptor, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, TextureSampleType,
+    TextureViewDimension, VertexState,
+};
+
+use crate::renderer::{camera::Camera, gbuffer::GBuffer, tilemap::TilemapId};
+
+use super::{create_shader, RenderPassData, GBUFFER_OUT_SOURCE};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlobalsUniform {
+    camera: Camera,
+    time: f32,
+    _pad: [f32; 3],
+}
+
+pub struct Tilemap {
+    pipeline: RenderPipeline,
+    tilemap_bgl: BindGroupLayout,
+    globals_bg: BindGroup,
+    globals_b: Buffer,
+    sampler: Sampler,
+}
+
+impl Tilemap {
+    pub fn new(device: &Device, gbuffer: &GBuffer) -> Self {
+        let shader = create_shader(
+            device,
+            "Tilemap Shader",
+            GBUFFER_OUT_SOURCE,
+            include_str!("../../shaders/tilemap.wgsl"),
+        );
+
+        let globals = GlobalsUniform {
+            camera: Camera::default(),
+            time: 0.0,
+            _pad: [0.0; 3],
+        };
+        let globals_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Tilemap Globals B"),
+            contents: bytemuck::bytes_of(&globals),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let globals_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tilemap Globals BGL"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let globals_bg = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Tilemap Globals BG"),
+            layout: &globals_bgl,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: globals_b.as_entire_binding(),
+            }],
+        });
+
+        let tilemap_bgl = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Tilemap BGL"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Uint,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // Tilesets are usually pixel art, nearest keeps tile edges crisp
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Tileset Sampler"),
+            ..Default::default()
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Tilemap Pipeline Layout"),
+            bind_group_layouts: &[&globals_bgl, &tilemap_bgl],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Tilemap Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &gbuffer.color_targets(BlendState::ALPHA_BLENDING, false),
+                compilation_options: Default::default(),
+            }),
+            primitive: Default::default(),
+            depth_stencil: Some(DepthStencilState {
+                format: gbuffer.depth_format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: gbuffer.multisample(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            tilemap_bgl,
+            globals_bg,
+            globals_b,
+            sampler,
+        }
+    }
+
+    pub fn execute(
+        &self,
+        data: &mut RenderPassData,
+        tilemaps: &[TilemapId],
+        layer: u32,
+        camera: &Camera,
+        time: f32,
+    ) {
+        if tilemaps.is_empty() {
+            return;
+        }
+
+        let globals = GlobalsUniform {
+            camera: *camera,
+            time,
+            _pad: [0.0; 3],
+        };
+        data.queue
+            .write_buffer(&self.globals_b, 0, bytemuck::bytes_of(&globals));
+
+        let tilemap_bgs: Vec<BindGroup> = tilemaps
+            .iter()
+            .map(|id| {
+                let tilemap = data.tilemaps.get(*id);
+                data.device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Tilemap BG"),
+                    layout: &self.tilemap_bgl,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: tilemap.params_b.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(&tilemap.tiles_view),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::Sampler(&self.sampler),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: BindingResource::TextureView(
+                                data.textures.view(tilemap.tileset),
+                            ),
+                        },
+                        BindGroupEntry {
+                            binding: 4,
+                            resource: tilemap.animations_b.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let views = data.gbuffer.layer_views(layer);
+        let mut rpass = data.encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("Tilemap Pass Layer {}", layer)),
+            color_attachments: &views.color_attachments(),
+            depth_stencil_attachment: Some(views.depth_attachment()),
+            ..Default::default()
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.globals_bg, &[]);
+
+        for tilemap_bg in &tilemap_bgs {
+            rpass.set_bind_group(1, tilemap_bg, &[]);
+            rpass.draw(0..6, 0..1);
+        }
+    }
+}
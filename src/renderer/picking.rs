@@ -0,0 +1,148 @@
+// Object picking from the G-buffer id target
+//
+// The geometry pass writes `encode_id(layer, index)` for every pixel an object covers,
+// where `index` is the object's position in the layer's objects passed to `render`.
+// Zero marks pixels without an object.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use wgpu::{
+    BufferAsyncError, BufferDescriptor, BufferSlice, BufferUsages, CommandEncoderDescriptor,
+    Device, Extent3d, MapMode, Origin3d, PollType, Queue, TexelCopyBufferInfo,
+    TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use super::gbuffer::GBuffer;
+
+const INDEX_BITS: u32 = 24;
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+
+pub(crate) fn encode_id(layer: u32, index: u32) -> u32 {
+    assert!(
+        index < INDEX_MASK,
+        "too many objects in layer {} to pick",
+        layer
+    );
+    (layer << INDEX_BITS) | (index + 1)
+}
+
+pub(crate) fn decode_id(id: u32) -> Option<(u32, u32)> {
+    (id != 0).then(|| (id >> INDEX_BITS, (id & INDEX_MASK) - 1))
+}
+
+// Ids of the `size` region at `origin` in G-buffer pixels, layer by layer and row by row.
+// The copy is submitted right away, the returned future resolves once a later
+// `Device::poll` finds it done and does not borrow anything.
+pub(crate) fn read_ids(
+    device: &Device,
+    queue: &Queue,
+    gbuffer: &GBuffer,
+    origin: [u32; 2],
+    size: [u32; 2],
+) -> impl Future<Output = Vec<u32>> + use<> {
+    let row_bytes = size[0] * 4;
+    let padded_row_bytes = row_bytes.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let readback_b = device.create_buffer(&BufferDescriptor {
+        label: Some("Picking Readback B"),
+        size: (padded_row_bytes * size[1] * gbuffer.layers) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Picking Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        TexelCopyTextureInfo {
+            texture: &gbuffer.id_texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: origin[0],
+                y: origin[1],
+                z: 0,
+            },
+            aspect: TextureAspect::All,
+        },
+        TexelCopyBufferInfo {
+            buffer: &readback_b,
+            layout: TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(size[1]),
+            },
+        },
+        Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: gbuffer.layers,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let mapped = Mapped::new(device, readback_b.slice(..));
+    async move {
+        mapped
+            .await
+            .expect("failed to map the picking readback buffer");
+
+        let data = readback_b.slice(..).get_mapped_range();
+        data.chunks(padded_row_bytes as usize)
+            .flat_map(|row| bytemuck::cast_slice::<u8, u32>(&row[..row_bytes as usize]).to_vec())
+            .collect()
+    }
+}
+
+// Resolves once a buffer is mapped for reading. The map callback wakes the task, on native
+// backends it runs from within `Device::poll`, which every poll of this future and every
+// `Renderer::render` call without blocking.
+struct Mapped {
+    device: Device,
+    state: Arc<Mutex<MapState>>,
+}
+
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+impl Mapped {
+    fn new(device: &Device, slice: BufferSlice) -> Self {
+        let state = Arc::new(Mutex::new(MapState::default()));
+        let sender = state.clone();
+        slice.map_async(MapMode::Read, move |mapped| {
+            let mut state = sender.lock().unwrap();
+            state.result = Some(mapped);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        Self {
+            device: device.clone(),
+            state,
+        }
+    }
+}
+
+impl Future for Mapped {
+    type Output = Result<(), BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _ = self.device.poll(PollType::Poll);
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use wgpu::{
+    util::{DeviceExt, TextureDataOrder},
+    Device, Extent3d, Queue, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureView,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureId(pub(crate) u32);
+
+// Textures uploaded by the user, referenced from materials by `TextureId`.
+// Color textures are stored as sRGB and decoded to linear when sampled, data such as
+// normal maps is sampled as it is.
+#[derive(Default)]
+pub struct Textures {
+    views: Vec<TextureView>,
+}
+
+impl Textures {
+    pub fn create(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        srgb: bool,
+    ) -> TextureId {
+        assert_eq!(
+            rgba.len(),
+            width as usize * height as usize * 4,
+            "texture data must be tightly packed RGBA8"
+        );
+
+        let id = TextureId(self.views.len() as u32);
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some(&format!("User Texture {}", id.0)),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: if srgb {
+                    TextureFormat::Rgba8UnormSrgb
+                } else {
+                    TextureFormat::Rgba8Unorm
+                },
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            rgba,
+        );
+        self.views.push(texture.create_view(&Default::default()));
+        id
+    }
+
+    pub fn view(&self, id: TextureId) -> &TextureView {
+        &self.views[id.0 as usize]
+    }
+}
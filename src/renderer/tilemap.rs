@@ -0,0 +1,233 @@
+// Tile-based layers drawn with a single quad
+//
+// Tiles are `u32`s where 0 is an empty tile and any other value is the
+// tileset index plus one in the low bits, combined with the flag bits below.
+// Tileset indices count left to right, top to bottom through the atlas.
+
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferUsages, Device, Extent3d, Origin3d, Queue, TexelCopyBufferLayout,
+    TexelCopyTextureInfo, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView,
+};
+
+use super::texture::TextureId;
+
+pub const TILE_FLIP_X: u32 = 1 << 31;
+pub const TILE_FLIP_Y: u32 = 1 << 30;
+/// Rotates the tile 90 degrees clockwise, applied before the flips.
+pub const TILE_ROTATE: u32 = 1 << 29;
+pub const TILE_INDEX_MASK: u32 = TILE_ROTATE - 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TilemapId(pub(crate) u32);
+
+/// Cycles `tile` through `frames` consecutive tileset indices.
+#[derive(Copy, Clone)]
+pub struct TileAnimation {
+    pub tile: u32,
+    pub frames: u32,
+    pub frame_time: f32,
+}
+
+pub struct TilemapDescriptor<'a> {
+    pub pos: [f32; 2],
+    pub tile_size: [f32; 2],
+    pub width: u32,
+    pub height: u32,
+    pub tiles: &'a [u32],
+    pub tileset: TextureId,
+    pub tileset_columns: u32,
+    pub tileset_rows: u32,
+    pub animations: &'a [TileAnimation],
+    /// Written to the layer's depth target like `Object::depth`.
+    pub depth: f32,
+}
+
+// GPU layout of the tilemap parameters, matches `Tilemap` in tilemap.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TilemapRaw {
+    pos: [f32; 2],
+    tile_size: [f32; 2],
+    map_size: [u32; 2],
+    tileset_size: [u32; 2],
+    depth: f32,
+    _pad: [f32; 3],
+}
+
+// GPU layout of a tileset entry, matches `TileAnimation` in tilemap.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct TileAnimationRaw {
+    frames: u32,
+    frame_time: f32,
+}
+
+pub(crate) struct TilemapBuffers {
+    pub params_b: Buffer,
+    pub animations_b: Buffer,
+    pub tiles_view: TextureView,
+    pub tileset: TextureId,
+    tiles_texture: Texture,
+    map_size: [u32; 2],
+    tileset_len: u32,
+}
+
+impl TilemapBuffers {
+    // Anything written has to fit the map and the tileset, the shader does not check again
+    fn write_tiles(&self, queue: &Queue, origin: [u32; 2], size: [u32; 2], tiles: &[u32]) {
+        let [x, y] = origin;
+        let [width, height] = size;
+        let fits =
+            |start: u32, len: u32, end: u32| start.checked_add(len).is_some_and(|e| e <= end);
+        assert!(
+            fits(x, width, self.map_size[0]) && fits(y, height, self.map_size[1]),
+            "tiles at {:?} of size {:?} run past the map of {:?}",
+            origin,
+            size,
+            self.map_size
+        );
+        let len = width.checked_mul(height).expect("tile region too large");
+        assert_eq!(tiles.len(), len as usize);
+        assert!(
+            tiles
+                .iter()
+                .all(|tile| tile & TILE_INDEX_MASK <= self.tileset_len),
+            "tile index outside of the tileset"
+        );
+
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.tiles_texture,
+                mip_level: 0,
+                origin: Origin3d { x, y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(tiles),
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+// Tilemaps uploaded by the user, referenced from draws by `TilemapId`
+#[derive(Default)]
+pub struct Tilemaps {
+    buffers: Vec<TilemapBuffers>,
+}
+
+impl Tilemaps {
+    pub fn create(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        desc: &TilemapDescriptor,
+    ) -> TilemapId {
+        let tileset_len = desc
+            .tileset_columns
+            .checked_mul(desc.tileset_rows)
+            .filter(|&len| len > 0)
+            .expect("tilesets need at least one tile");
+        assert!(
+            desc.width > 0 && desc.height > 0,
+            "tilemaps cannot be empty"
+        );
+
+        let id = TilemapId(self.buffers.len() as u32);
+
+        let raw = TilemapRaw {
+            pos: desc.pos,
+            tile_size: desc.tile_size,
+            map_size: [desc.width, desc.height],
+            tileset_size: [desc.tileset_columns, desc.tileset_rows],
+            depth: desc.depth,
+            _pad: [0.0; 3],
+        };
+        let params_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Tilemap {} Params B", id.0)),
+            contents: bytemuck::bytes_of(&raw),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        let mut animations = vec![TileAnimationRaw::default(); tileset_len as usize];
+        for animation in desc.animations {
+            assert!(
+                animation.tile < tileset_len
+                    && animation.tile.saturating_add(animation.frames) <= tileset_len,
+                "animation of tile {} with {} frames runs past the tileset of {} tiles",
+                animation.tile,
+                animation.frames,
+                tileset_len
+            );
+            assert!(
+                animation.frame_time > 0.0,
+                "animation of tile {} needs a positive frame time",
+                animation.tile
+            );
+            animations[animation.tile as usize] = TileAnimationRaw {
+                frames: animation.frames,
+                frame_time: animation.frame_time,
+            };
+        }
+        let animations_b = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(&format!("Tilemap {} Animations B", id.0)),
+            contents: bytemuck::cast_slice(&animations),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let tiles_texture = device.create_texture(&TextureDescriptor {
+            label: Some(&format!("Tilemap {} Tiles", id.0)),
+            size: Extent3d {
+                width: desc.width,
+                height: desc.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Uint,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let tiles_view = tiles_texture.create_view(&Default::default());
+
+        let buffers = TilemapBuffers {
+            params_b,
+            animations_b,
+            tiles_view,
+            tileset: desc.tileset,
+            tiles_texture,
+            map_size: [desc.width, desc.height],
+            tileset_len,
+        };
+        buffers.write_tiles(queue, [0, 0], [desc.width, desc.height], desc.tiles);
+
+        self.buffers.push(buffers);
+        id
+    }
+
+    // Overwrites the `size` region of tiles starting at `origin`
+    pub fn update(
+        &self,
+        queue: &Queue,
+        id: TilemapId,
+        origin: [u32; 2],
+        size: [u32; 2],
+        tiles: &[u32],
+    ) {
+        self.buffers[id.0 as usize].write_tiles(queue, origin, size, tiles);
+    }
+
+    pub(crate) fn get(&self, id: TilemapId) -> &TilemapBuffers {
+        &self.buffers[id.0 as usize]
+    }
+}
@@ -0,0 +1,65 @@
+// Ambient occlusion cast by a layer onto the ones beneath it. `horizontal` takes the
+// layer's alpha moved by the offset and blurs it along rows, `vertical` blurs that along
+// columns into how much the layer shades each pixel below it. The composite scales the
+// result by the strength of each pair of layers.
+
+struct Params {
+    // G-buffer pixels the shade is moved by
+    offset: vec2<i32>,
+    radius: u32,
+    layer: u32,
+};
+
+@group(0) @binding(0) var input: texture_2d_array<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Gaussian reaching about zero at the radius
+fn weight(x: i32) -> f32 {
+    let sigma = max(f32(params.radius), 1.0) * 0.5;
+    return exp(-f32(x * x) / (2.0 * sigma * sigma));
+}
+
+// Nothing casts from outside the G-buffer
+fn tap(pixel: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(input));
+    if (any(pixel < vec2<i32>(0)) || any(pixel >= size)) {
+        return vec4<f32>(0.0);
+    }
+    return textureLoad(input, pixel, params.layer, 0);
+}
+
+// Reads the layer's colors
+@fragment
+fn fs_horizontal(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(pos.xy) - params.offset;
+    let radius = i32(params.radius);
+    var sum = 0.0;
+    var total = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        let w = weight(x);
+        sum += tap(pixel + vec2<i32>(x, 0)).a * w;
+        total += w;
+    }
+    return vec4<f32>(sum / total, 0.0, 0.0, 1.0);
+}
+
+// Reads the horizontal blur
+@fragment
+fn fs_vertical(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(pos.xy);
+    let radius = i32(params.radius);
+    var sum = 0.0;
+    var total = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        let w = weight(y);
+        sum += tap(pixel + vec2<i32>(0, y)).r * w;
+        total += w;
+    }
+    return vec4<f32>(sum / total, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,95 @@
+// Bloom as a chain of half sized targets. `prefilter` keeps what glows out of the scene,
+// `downsample` blurs it into each smaller level and `upsample` adds every level back onto
+// the one above it, leaving the glow of all of them in the first.
+
+struct Params {
+    // Luminance a pixel has to exceed to glow, the rest of it glows
+    threshold: f32,
+    intensity: f32,
+    // 0 takes the composited scene, 1 the emissive pixels of the layers
+    source: u32,
+};
+
+const LUMINANCE: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+@group(0) @binding(3) var colors: texture_2d_array<f32>;
+@group(0) @binding(4) var emissives: texture_2d_array<f32>;
+
+struct VSOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Fullscreen triangle, uv follows the target's rows
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> VSOut {
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    var out: VSOut;
+    out.pos = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// Emitted light left visible by the layers above, blended like the composite does
+fn emissive(uv: vec2<f32>) -> vec3<f32> {
+    // The scene has the G-buffer's rows flipped
+    let gbuffer_uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    var glow = vec3<f32>(0.0);
+    for (var layer = 0u; layer < textureNumLayers(colors); layer++) {
+        let alpha = textureSampleLevel(colors, input_sampler, gbuffer_uv, layer, 0.0).a;
+        let emitted = textureSampleLevel(emissives, input_sampler, gbuffer_uv, layer, 0.0).rgb;
+        glow = emitted * alpha + glow * (1.0 - alpha);
+    }
+    return glow;
+}
+
+@fragment
+fn fs_prefilter(in: VSOut) -> @location(0) vec4<f32> {
+    // Each target pixel covers four of the scene's, the filtering averages them
+    var color = textureSampleLevel(input, input_sampler, in.uv, 0.0).rgb;
+    if (params.source == 1u) {
+        color = emissive(in.uv);
+    }
+    // Scaled down as a whole to keep the hue of what glows
+    let luminance = dot(color, LUMINANCE);
+    let glow = max(luminance - params.threshold, 0.0) / max(luminance, 1e-4);
+    return vec4<f32>(color * glow * params.intensity, 1.0);
+}
+
+fn tap(uv: vec2<f32>, texel: vec2<f32>, x: f32, y: f32) -> vec3<f32> {
+    return textureSampleLevel(input, input_sampler, uv + texel * vec2<f32>(x, y), 0.0).rgb;
+}
+
+// 13 bilinear taps over a 4x4 block of the level above, weighted so a lone bright pixel
+// does not flicker as it moves
+@fragment
+fn fs_downsample(in: VSOut) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(input));
+    let uv = in.uv;
+    let center = tap(uv, texel, 0.0, 0.0);
+    let inner = tap(uv, texel, -1.0, -1.0) + tap(uv, texel, 1.0, -1.0)
+        + tap(uv, texel, -1.0, 1.0) + tap(uv, texel, 1.0, 1.0);
+    let corners = tap(uv, texel, -2.0, -2.0) + tap(uv, texel, 2.0, -2.0)
+        + tap(uv, texel, -2.0, 2.0) + tap(uv, texel, 2.0, 2.0);
+    let edges = tap(uv, texel, 0.0, -2.0) + tap(uv, texel, -2.0, 0.0)
+        + tap(uv, texel, 2.0, 0.0) + tap(uv, texel, 0.0, 2.0);
+    let color = center * 0.125 + inner * 0.125 + corners * 0.03125 + edges * 0.0625;
+    return vec4<f32>(color, 1.0);
+}
+
+// 3x3 tent over the level below, added onto this level by the blending
+@fragment
+fn fs_upsample(in: VSOut) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(input));
+    let uv = in.uv;
+    let center = tap(uv, texel, 0.0, 0.0);
+    let edges = tap(uv, texel, 0.0, -1.0) + tap(uv, texel, -1.0, 0.0)
+        + tap(uv, texel, 1.0, 0.0) + tap(uv, texel, 0.0, 1.0);
+    let corners = tap(uv, texel, -1.0, -1.0) + tap(uv, texel, 1.0, -1.0)
+        + tap(uv, texel, -1.0, 1.0) + tap(uv, texel, 1.0, 1.0);
+    let color = center * 0.25 + edges * 0.125 + corners * 0.0625;
+    return vec4<f32>(color, 1.0);
+}
@@ -5,7 +5,22 @@ struct Params {
 
 @group(0) @binding(0) var sampler0: sampler;
 @group(0) @binding(1) var textures: texture_2d_array<f32>;
+@group(0) @binding(2) var normals: texture_2d_array<f32>;
+@group(0) @binding(3) var emissives: texture_2d_array<f32>;
+// Per-pixel depth of each layer, 1.0 where nothing was drawn
+@group(0) @binding(4) var depths: texture_2d_array<f32>;
+@group(0) @binding(5) var depth_sampler: sampler;
+@group(0) @binding(6) var lights: texture_2d_array<f32>;
 @group(1) @binding(0) var<uniform> params: Params;
+// Light mask of each layer, 0 for unlit layers whose light target is never written
+@group(1) @binding(1) var<storage, read> masks: array<u32>;
+// How strongly each layer shades each one beneath it, at `caster * layers + receiver`
+@group(1) @binding(2) var<storage, read> occlusion_strengths: array<f32>;
+// Light bounced by global illumination, black without it
+@group(2) @binding(0) var indirect: texture_2d<f32>;
+@group(2) @binding(1) var indirect_sampler: sampler;
+// Blurred alpha of each layer casting ambient occlusion, moved by its offset
+@group(3) @binding(0) var shades: texture_2d_array<f32>;
 
 struct VSOut {
     @builtin(position) pos: vec4<f32>,
@@ -37,6 +52,30 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VSOut {
     return out;
 }
 
+fn layer_light(uv: vec2<f32>, layer: u32) -> vec4<f32> {
+    if (masks[layer] == 0u) {
+        return vec4<f32>(1.0);
+    }
+    return textureSample(lights, sampler0, uv, layer);
+}
+
+// Share of the layer's color left by the ambient occlusion of the layers above it
+fn occlusion(uv: vec2<f32>, layer: u32) -> f32 {
+    let layer_count = textureNumLayers(textures);
+    var lit = 1.0;
+    for (var caster = layer + 1u; caster < layer_count; caster++) {
+        let strength = occlusion_strengths[caster * layer_count + layer];
+        if (strength > 0.0) {
+            lit *= 1.0 - strength * textureSampleLevel(shades, indirect_sampler, uv, caster, 0.0).r;
+        }
+    }
+    return lit;
+}
+
+fn layer_depth(uv: vec2<f32>, layer: u32) -> f32 {
+    return textureSampleLevel(depths, depth_sampler, uv, layer, 0.0).r;
+}
+
 @fragment
 fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
     let layer_count = textureNumLayers(textures);
@@ -44,15 +83,23 @@ fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
 
     if (params.mode == 0u) {
         // Composite
+        let bounced = textureSample(indirect, indirect_sampler, in.uv).rgb;
         for (var layer: u32 = 0u; layer < layer_count; layer = layer + 1u) {
             let sample = textureSample(textures, sampler0, in.uv, layer);
+            var light = layer_light(in.uv, layer);
+            if (masks[layer] != 0u) {
+                light = vec4(light.rgb + bounced, light.a);
+            }
+            // Emitted light is not lit, it goes on top of the layer's lit color
+            let emissive = textureSample(emissives, sampler0, in.uv, layer);
+            let rgb = sample.rgb * light.rgb * occlusion(in.uv, layer) + emissive.rgb;
             color = vec4(
-                sample.rgb * sample.a + color.rgb * (1.0 - sample.a),
+                rgb * sample.a + color.rgb * (1.0 - sample.a),
                 sample.a + color.a * (1.0 - sample.a)
             );
         }
-    } else if (params.mode == 1u) {
-        // Grid view
+    } else {
+        // Grid view of the colors, normals or emissive
         let grid_cols = u32(ceil(sqrt(f32(layer_count))));
         let grid_rows = u32(ceil(f32(layer_count) / f32(grid_cols)));
 
@@ -73,8 +120,17 @@ fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
             fract(in.uv.y * f32(grid_rows))
         );
 
-        color = textureSample(textures, sampler0, local_uv, layer);
+        if (params.mode == 2u) {
+            color = textureSample(normals, sampler0, local_uv, layer);
+        } else if (params.mode == 3u) {
+            color = textureSample(emissives, sampler0, local_uv, layer);
+        } else if (params.mode == 4u) {
+            color = vec4(vec3(layer_depth(local_uv, layer)), 1.0);
+        } else if (params.mode == 5u) {
+            color = layer_light(local_uv, layer);
+        } else {
+            color = textureSample(textures, sampler0, local_uv, layer);
+        }
     }
-
     return color;
 }
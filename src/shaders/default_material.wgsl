@@ -0,0 +1,3 @@
+fn material(input: VSOut) -> vec4<f32> {
+    return input.color;
+}
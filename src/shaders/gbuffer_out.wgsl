@@ -0,0 +1,11 @@
+// Targets of a layer written by the passes drawing into it after the geometry pass, which
+// adds object ids of its own. Put in front of mesh.wgsl, particles.wgsl and tilemap.wgsl.
+
+struct GBufferOut {
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) emissive: vec4<f32>,
+};
+
+// Not normal mapped, faces straight at the viewer
+const FLAT_NORMAL: vec3<f32> = vec3<f32>(0.5, 0.5, 1.0);
@@ -2,8 +2,17 @@ struct Object {
     pos: vec2<f32>,
     size: vec2<f32>,
     color: vec4<f32>,
+    emissive: vec3<f32>,
+    flags: u32,
+    id: u32,
+    depth: f32,
 };
 
+const OBJECT_HIDDEN: u32 = 1u;
+const OBJECT_FLIP_X: u32 = 2u;
+const OBJECT_FLIP_Y: u32 = 4u;
+const OBJECT_SCREEN_SPACE: u32 = 8u;
+
 struct Camera {
     pos: vec2<f32>,
     size: vec2<f32>,
@@ -16,6 +25,12 @@ struct Frame {
 @group(0) @binding(0) var<storage, read> objects: array<Object>;
 @group(1) @binding(0) var<uniform> frame: Frame;
 @group(1) @binding(1) var<uniform> camera: Camera;
+// Signed distance in G-buffer pixels to the nearest occluder, negative inside occluders.
+// Read with `textureLoad` at `VSOut::pos`. Layers drawn before the last occluder layer see
+// the previous frame's.
+@group(1) @binding(2) var sdf: texture_2d<f32>;
+@group(3) @binding(0) var normal_sampler: sampler;
+@group(3) @binding(1) var normal_map: texture_2d<f32>;
 
 var<private> quad_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
     vec2<f32>(0.0, 0.0),
@@ -29,6 +44,17 @@ var<private> quad_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
 struct VSOut {
     @builtin(position) pos: vec4<f32>,
     @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) @interpolate(flat) flags: u32,
+    @location(3) @interpolate(flat) emissive: vec3<f32>,
+    @location(4) @interpolate(flat) id: u32,
+};
+
+struct GBufferOut {
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) emissive: vec4<f32>,
+    @location(3) id: u32,
 };
 
 @vertex
@@ -37,25 +63,61 @@ fn vs_main(@builtin(vertex_index) vi: u32,
     let quad = quad_positions[vi];
     let obj = objects[ii];
 
+    var out: VSOut;
+    if ((obj.flags & OBJECT_HIDDEN) != 0u) {
+        // Hidden objects collapse to a degenerate triangle
+        out.pos = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+        return out;
+    }
+
     let pixel_pos = obj.pos + quad * obj.size;
 
-    let cam_x = pixel_pos.x - camera.pos.x;
-    let cam_y = pixel_pos.y - camera.pos.y;
+    var norm: vec2<f32>;
+    if ((obj.flags & OBJECT_SCREEN_SPACE) != 0u) {
+        norm = pixel_pos / vec2<f32>(frame.size);
+    } else {
+        norm = (pixel_pos - camera.pos) / camera.size;
+    }
 
-    let norm_x = cam_x / camera.size.x;
-    let norm_y = cam_y / camera.size.y;
+    let ndc_x = norm.x * 2.0 - 1.0;
+    let ndc_y = 1.0 - norm.y * 2.0;
 
-    let ndc_x = norm_x * 2.0 - 1.0;
-    let ndc_y = 1.0 - norm_y * 2.0;
+    var uv = quad;
+    if ((obj.flags & OBJECT_FLIP_X) != 0u) {
+        uv.x = 1.0 - uv.x;
+    }
+    if ((obj.flags & OBJECT_FLIP_Y) != 0u) {
+        uv.y = 1.0 - uv.y;
+    }
 
-    var out: VSOut;
-    out.pos = vec4<f32>(ndc_x, ndc_y, 0.0, 1.0);
+    out.pos = vec4<f32>(ndc_x, ndc_y, obj.depth, 1.0);
     out.color = obj.color;
+    out.uv = uv;
+    out.flags = obj.flags;
+    out.emissive = obj.emissive;
+    out.id = obj.id;
 
     return out;
 }
 
+// `material` is provided by the material source appended to this file
 @fragment
-fn fs_main(input: VSOut) -> @location(0) vec4<f32> {
-    return input.color;
+fn fs_main(input: VSOut) -> GBufferOut {
+    var out: GBufferOut;
+    out.color = material(input);
+
+    // Normal maps point green up, the G-buffer stores normals in pixel space with y down
+    var normal = textureSample(normal_map, normal_sampler, input.uv).xyz * 2.0 - 1.0;
+    normal.y = -normal.y;
+    if ((input.flags & OBJECT_FLIP_X) != 0u) {
+        normal.x = -normal.x;
+    }
+    if ((input.flags & OBJECT_FLIP_Y) != 0u) {
+        normal.y = -normal.y;
+    }
+    out.normal = vec4<f32>(normalize(normal) * 0.5 + 0.5, out.color.a);
+    out.emissive = vec4<f32>(input.emissive, out.color.a);
+    out.id = input.id;
+
+    return out;
 }
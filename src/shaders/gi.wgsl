@@ -0,0 +1,146 @@
+// 2D global illumination with radiance cascades.
+//
+// `scene` reduces the layers to the pixels that light bounces off, emissive ones and lit
+// occluders, with their outgoing light in rgb and 1.0 in alpha. Every cascade holds probes
+// casting rays over one interval of distances, cascade `i` has probes every
+// `PROBE_SPACING << i` scene pixels, each with a `2 << i` square block of directions, and
+// rays four times as long as the cascade below. `cascade` runs from the top cascade down,
+// merging what the rays of the cascade above see past their own interval. `integrate`
+// averages cascade 0's directions into the light reaching each probe.
+
+struct Params {
+    // Bit per occluder layer
+    occluders: u32,
+    // Bit per layer with a light target
+    lit: u32,
+    cascade: u32,
+    cascade_count: u32,
+    // Share of the direct light occluders reflect
+    bounce: f32,
+};
+
+const TAU: f32 = 6.28318530718;
+// Scene pixels between cascade 0 probes, and the length of their rays
+const PROBE_SPACING: u32 = 2u;
+
+@group(0) @binding(0) var colors: texture_2d_array<f32>;
+@group(0) @binding(1) var emissives: texture_2d_array<f32>;
+@group(0) @binding(2) var lights: texture_2d_array<f32>;
+@group(0) @binding(3) var scene_in: texture_2d<f32>;
+@group(0) @binding(4) var upper: texture_2d<f32>;
+@group(0) @binding(5) var output: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(6) var<uniform> params: Params;
+
+@compute @workgroup_size(8, 8)
+fn scene(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (any(id.xy >= size)) {
+        return;
+    }
+    // Nearest G-buffer pixel, the scene is usually smaller
+    let gbuffer_size = textureDimensions(colors);
+    let pixel = vec2<u32>((vec2<f32>(id.xy) + 0.5) * vec2<f32>(gbuffer_size) / vec2<f32>(size));
+
+    var radiance = vec3<f32>(0.0);
+    var solid = false;
+    for (var layer = 0u; layer < textureNumLayers(colors); layer++) {
+        let emissive = textureLoad(emissives, pixel, layer, 0).rgb;
+        if (any(emissive > vec3<f32>(0.0))) {
+            radiance += emissive;
+            solid = true;
+        }
+        let color = textureLoad(colors, pixel, layer, 0);
+        if ((params.occluders & (1u << layer)) != 0u && color.a > 0.5) {
+            var light = vec3<f32>(1.0);
+            if ((params.lit & (1u << layer)) != 0u) {
+                light = textureLoad(lights, pixel, layer, 0).rgb;
+            }
+            radiance += color.rgb * light * params.bounce;
+            solid = true;
+        }
+    }
+    textureStore(output, id.xy, vec4<f32>(radiance, select(0.0, 1.0, solid)));
+}
+
+// Radiance of direction `direction` of cascade `cascade + 1` at `pos`, interpolated between
+// its four nearest probes. Each direction splits into four in the cascade above.
+fn merge_upper(pos: vec2<f32>, direction: u32) -> vec4<f32> {
+    let block = 2u << (params.cascade + 1u);
+    let spacing = f32(PROBE_SPACING << (params.cascade + 1u));
+    let probes = vec2<i32>(textureDimensions(upper) / block);
+
+    let f = pos / spacing - 0.5;
+    let base = vec2<i32>(floor(f));
+    let weight = fract(f);
+
+    var radiance = vec4<f32>(0.0);
+    for (var corner = 0u; corner < 4u; corner++) {
+        let offset = vec2<i32>(i32(corner & 1u), i32(corner >> 1u));
+        let probe = clamp(base + offset, vec2<i32>(0), probes - 1);
+        let w = mix(1.0 - weight, weight, vec2<f32>(offset));
+
+        var sum = vec4<f32>(0.0);
+        for (var k = 0u; k < 4u; k++) {
+            let d = direction * 4u + k;
+            let texel = probe * i32(block) + vec2<i32>(i32(d % block), i32(d / block));
+            sum += textureLoad(upper, texel, 0);
+        }
+        radiance += sum * 0.25 * w.x * w.y;
+    }
+    return radiance;
+}
+
+@compute @workgroup_size(8, 8)
+fn cascade(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (any(id.xy >= size)) {
+        return;
+    }
+    let block = 2u << params.cascade;
+    let spacing = f32(PROBE_SPACING << params.cascade);
+    let probe = id.xy / block;
+    let direction = (id.y % block) * block + id.x % block;
+
+    let pos = (vec2<f32>(probe) + 0.5) * spacing;
+    let angle = (f32(direction) + 0.5) / f32(block * block) * TAU;
+    let ray = vec2<f32>(cos(angle), sin(angle));
+    // Intervals of 1, 4, 16... times the spacing, each starting where the last ended
+    let scale = f32(1u << (2u * params.cascade));
+    let start = f32(PROBE_SPACING) * (scale - 1.0) / 3.0;
+    let end = start + f32(PROBE_SPACING) * scale;
+
+    // One step per scene pixel, anything coarser jumps over thin walls
+    // Light seen in rgb, how much passes through in alpha
+    var radiance = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    let scene_size = vec2<i32>(textureDimensions(scene_in));
+    for (var t = start; t < end; t += 1.0) {
+        let pixel = vec2<i32>(floor(pos + ray * t));
+        if (any(pixel < vec2<i32>(0)) || any(pixel >= scene_size)) {
+            break;
+        }
+        let hit = textureLoad(scene_in, pixel, 0);
+        if (hit.a > 0.5) {
+            radiance = vec4<f32>(hit.rgb, 0.0);
+            break;
+        }
+    }
+
+    if (radiance.a > 0.0 && params.cascade + 1u < params.cascade_count) {
+        let far = merge_upper(pos, direction);
+        radiance = vec4<f32>(radiance.rgb + far.rgb * radiance.a, radiance.a * far.a);
+    }
+    textureStore(output, id.xy, radiance);
+}
+
+@compute @workgroup_size(8, 8)
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (any(id.xy >= size)) {
+        return;
+    }
+    var sum = vec3<f32>(0.0);
+    for (var d = 0u; d < 4u; d++) {
+        sum += textureLoad(upper, id.xy * 2u + vec2<u32>(d & 1u, d >> 1u), 0).rgb;
+    }
+    textureStore(output, id.xy, vec4<f32>(sum * 0.25, 1.0));
+}
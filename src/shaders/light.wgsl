@@ -0,0 +1,24 @@
+// Layout of a light in the frame's light buffer. Put in front of lighting.wgsl,
+// light_culling.wgsl and shadows.wgsl.
+
+struct Light {
+    pos: vec2<f32>,
+    radius: f32,
+    height: f32,
+    color: vec3<f32>,
+    falloff: f32,
+    direction: vec2<f32>,
+    cos_inner: f32,
+    cos_outer: f32,
+    // Matched against the layer's mask
+    mask: u32,
+    // Radius of the source, 0 for hard shadows
+    size: f32,
+    // Layer in `cookies` plus one, 0 without a cookie
+    cookie: u32,
+    cookie_rotation: f32,
+    // Relative to the radius
+    cookie_scale: vec2<f32>,
+    // Row in the shadow map plus one, 0 for lights without one
+    shadow_row: u32,
+};
@@ -0,0 +1,63 @@
+// Bins the frame's lights into screen tiles of the G-buffer. Each workgroup tests every
+// light's circle against the world rectangle its tile covers and lists the ones touching
+// it, which is all the lighting shader loops over for the tile's pixels.
+
+struct Camera {
+    pos: vec2<f32>,
+    size: vec2<f32>,
+}
+
+struct Params {
+    // G-buffer size in pixels
+    size: vec2<u32>,
+    tiles: vec2<u32>,
+};
+
+// Matches `TILE_SIZE` and `MAX_TILE_LIGHTS` in lighting.rs
+const TILE_SIZE: u32 = 16u;
+const MAX_TILE_LIGHTS: u32 = 256u;
+const WORKGROUP_SIZE: u32 = 64u;
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<storage, read> lights: array<Light>;
+@group(1) @binding(1) var<storage, read_write> tile_counts: array<u32>;
+// `MAX_TILE_LIGHTS` light indices per tile, the first `tile_counts` of them used
+@group(1) @binding(2) var<storage, read_write> tile_lights: array<u32>;
+@group(1) @binding(3) var<uniform> params: Params;
+
+var<workgroup> count: atomic<u32>;
+
+@compute @workgroup_size(64)
+fn cull(@builtin(workgroup_id) tile: vec3<u32>,
+        @builtin(local_invocation_index) index: u32) {
+    if (index == 0u) {
+        atomicStore(&count, 0u);
+    }
+    workgroupBarrier();
+
+    let tile_index = tile.y * params.tiles.x + tile.x;
+    // Pixels the tile covers, the last ones may be cut off by the G-buffer's edge
+    let min_pixel = tile.xy * TILE_SIZE;
+    let max_pixel = min(min_pixel + TILE_SIZE, params.size);
+    let scale = camera.size / vec2<f32>(params.size);
+    let min_world = camera.pos + vec2<f32>(min_pixel) * scale;
+    let max_world = camera.pos + vec2<f32>(max_pixel) * scale;
+
+    for (var i = index; i < arrayLength(&lights); i += WORKGROUP_SIZE) {
+        let light = lights[i];
+        let closest = clamp(light.pos, min_world, max_world);
+        if (distance(closest, light.pos) >= light.radius) {
+            continue;
+        }
+        // Lights past the tile's capacity are dropped
+        let slot = atomicAdd(&count, 1u);
+        if (slot < MAX_TILE_LIGHTS) {
+            tile_lights[tile_index * MAX_TILE_LIGHTS + slot] = i;
+        }
+    }
+
+    workgroupBarrier();
+    if (index == 0u) {
+        tile_counts[tile_index] = min(atomicLoad(&count), MAX_TILE_LIGHTS);
+    }
+}
@@ -0,0 +1,171 @@
+struct Layer {
+    index: u32,
+    mask: u32,
+    shadowed: u32,
+};
+
+struct Camera {
+    pos: vec2<f32>,
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<storage, read> lights: array<Light>;
+// Lights touching each tile, binned by light_culling.wgsl
+@group(1) @binding(1) var<storage, read> tile_counts: array<u32>;
+@group(1) @binding(2) var<storage, read> tile_lights: array<u32>;
+// Every cookie, resampled to one size
+@group(1) @binding(3) var cookies: texture_2d_array<f32>;
+@group(1) @binding(4) var cookie_sampler: sampler;
+// `n * 0.5 + 0.5` in pixel space with y down
+@group(2) @binding(0) var normals: texture_2d_array<f32>;
+@group(2) @binding(1) var<uniform> layer: Layer;
+// Row per light of how far each direction reaches before an occluder, as a fraction of the
+// light's radius
+@group(2) @binding(2) var shadow_map: texture_2d<f32>;
+// Signed distance in pixels to the nearest occluder
+@group(2) @binding(3) var sdf: texture_2d<f32>;
+
+// False goes through every light for every pixel, as a baseline for the tiles
+override CULLING: bool = true;
+// Matches `TILE_SIZE` and `MAX_TILE_LIGHTS` in lighting.rs
+const TILE_SIZE: u32 = 16u;
+const MAX_TILE_LIGHTS: u32 = 256u;
+const TAU: f32 = 6.28318530718;
+// Longest soft shadow trace, in SDF samples
+const TRACE_STEPS: i32 = 64;
+// Nothing past the G-buffer's edges occludes
+const FAR: f32 = 16384.0;
+
+// Single triangle covering the whole light target
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn sdf_at(p: vec2<f32>) -> f32 {
+    let pixel = vec2<i32>(floor(p));
+    if (any(pixel < vec2<i32>(0)) || any(pixel >= vec2<i32>(textureDimensions(sdf)))) {
+        return FAR;
+    }
+    return textureLoad(sdf, pixel, 0).r;
+}
+
+// Sphere traces the SDF from `pixel` towards the light. An occluder passing `d` from the
+// ray at `t` leaves the light visible when `d * total / t` clears the light's disk, so the
+// penumbra widens with the light's size and the distance behind the occluder. Rays
+// through an occluder are fully shadowed.
+fn soft_shadow(pixel: vec2<f32>, light: Light) -> f32 {
+    let scale = vec2<f32>(textureDimensions(sdf)) / camera.size;
+    let to_light = (light.pos - camera.pos) * scale - pixel;
+    let total = length(to_light);
+    if (total < 1.0) {
+        return 1.0;
+    }
+    let direction = to_light / total;
+    let size = light.size * scale.x;
+
+    var visibility = 1.0;
+    var t = 0.0;
+    for (var i = 0; i < TRACE_STEPS && t < total; i++) {
+        let d = sdf_at(pixel + direction * t);
+        if (d <= 0.0) {
+            return 0.0;
+        }
+        visibility = min(visibility, d * total / (max(t, 1.0) * size));
+        t += max(d, 1.0);
+    }
+    return smoothstep(0.0, 1.0, visibility);
+}
+
+// Cookie of `light` at `offset` from it in world space, rotated and scaled with the light.
+// Nothing gets through past its edges.
+fn cookie(light: Light, offset: vec2<f32>) -> vec3<f32> {
+    let c = cos(light.cookie_rotation);
+    let s = sin(light.cookie_rotation);
+    let local = vec2<f32>(c * offset.x + s * offset.y, c * offset.y - s * offset.x);
+    let uv = local / (light.radius * light.cookie_scale) * 0.5 + 0.5;
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0))) {
+        return vec3<f32>(0.0);
+    }
+    let sample = textureSampleLevel(cookies, cookie_sampler, uv, light.cookie - 1u, 0.0);
+    return sample.rgb * sample.a;
+}
+
+// Light from one light at `pixel` showing `world_pos`
+fn shade(pixel: vec2<f32>, world_pos: vec2<f32>, normal: vec3<f32>, index: u32) -> vec3<f32> {
+    let light = lights[index];
+    if ((light.mask & layer.mask) == 0u) {
+        return vec3<f32>(0.0);
+    }
+
+    let to_light = light.pos - world_pos;
+    let distance = length(to_light);
+    if (distance >= light.radius) {
+        return vec3<f32>(0.0);
+    }
+    let attenuation = pow(1.0 - distance / light.radius, light.falloff);
+
+    var color = light.color;
+    if (light.cookie != 0u) {
+        color *= cookie(light, -to_light);
+    }
+
+    // Soft shadows from sized lights, hard ones past the first occluder give or take a
+    // pixel from the others. Lights past the shadow map's rows are unshadowed.
+    var shadow = 1.0;
+    let row = light.shadow_row - 1u;
+    if (layer.shadowed != 0u && light.size > 0.0) {
+        shadow = soft_shadow(pixel, light);
+    } else if (layer.shadowed != 0u && light.shadow_row != 0u
+        && row < textureDimensions(shadow_map).y) {
+        let width = textureDimensions(shadow_map).x;
+        let angle = fract(atan2(-to_light.y, -to_light.x) / TAU);
+        let column = min(u32(angle * f32(width)), width - 1u);
+        let reach = textureLoad(shadow_map, vec2<u32>(column, row), 0).r * light.radius;
+        let pixel_size = camera.size.x / f32(textureDimensions(normals).x);
+        if (distance > reach + pixel_size * 1.5) {
+            return vec3<f32>(0.0);
+        }
+    }
+
+    // Spot lights fade out between their inner and outer cone, point lights have no cone
+    var cone = 1.0;
+    if (light.cos_outer > -1.0 && distance > 0.0) {
+        let angle_cos = dot(-to_light / distance, light.direction);
+        // Hard edged when both angles match
+        let cos_inner = max(light.cos_inner, light.cos_outer + 0.0001);
+        cone = smoothstep(light.cos_outer, cos_inner, angle_cos);
+    }
+
+    let direction = normalize(vec3<f32>(to_light, light.height));
+    let diffuse = max(dot(normal, direction), 0.0);
+
+    return color * attenuation * cone * diffuse * shadow;
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = textureDimensions(normals).xy;
+    let world_pos = camera.pos + pos.xy / vec2<f32>(size) * camera.size;
+    // Light on uncovered pixels is never shown, whatever their cleared normal gives
+    let normal = normalize(textureLoad(normals, vec2<u32>(pos.xy), layer.index, 0).xyz * 2.0 - 1.0);
+
+    var color = vec3<f32>(0.0);
+    if (!CULLING) {
+        for (var i = 0u; i < arrayLength(&lights); i++) {
+            color += shade(pos.xy, world_pos, normal, i);
+        }
+        return vec4<f32>(color, 0.0);
+    }
+
+    let tiles_x = (size.x + TILE_SIZE - 1u) / TILE_SIZE;
+    let tile = vec2<u32>(pos.xy) / TILE_SIZE;
+    let tile_index = tile.y * tiles_x + tile.x;
+
+    for (var i = 0u; i < tile_counts[tile_index]; i++) {
+        color += shade(pos.xy, world_pos, normal, tile_lights[tile_index * MAX_TILE_LIGHTS + i]);
+    }
+    return vec4<f32>(color, 0.0);
+}
@@ -0,0 +1,62 @@
+struct Camera {
+    pos: vec2<f32>,
+    size: vec2<f32>,
+}
+
+struct Transform {
+    translation: vec2<f32>,
+    scale: vec2<f32>,
+    rotation: f32,
+    depth: f32,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> transform: Transform;
+@group(2) @binding(0) var sampler0: sampler;
+@group(2) @binding(1) var texture0: texture_2d<f32>;
+
+struct VSIn {
+    @location(0) pos: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VSOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+// Vertex colors are authored in sRGB
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let low = c / 12.92;
+    let high = pow((c + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, c <= vec3<f32>(0.04045));
+}
+
+@vertex
+fn vs_main(input: VSIn) -> VSOut {
+    let scaled = input.pos * transform.scale;
+    let c = cos(transform.rotation);
+    let s = sin(transform.rotation);
+    let rotated = vec2<f32>(scaled.x * c - scaled.y * s, scaled.x * s + scaled.y * c);
+    let pixel_pos = transform.translation + rotated;
+
+    let norm = (pixel_pos - camera.pos) / camera.size;
+
+    var out: VSOut;
+    out.pos = vec4<f32>(norm.x * 2.0 - 1.0, 1.0 - norm.y * 2.0, transform.depth, 1.0);
+    out.color = vec4<f32>(srgb_to_linear(input.color.rgb), input.color.a);
+    out.uv = input.uv;
+
+    return out;
+}
+
+@fragment
+fn fs_main(input: VSOut) -> GBufferOut {
+    var out: GBufferOut;
+    out.color = textureSample(texture0, sampler0, input.uv) * input.color;
+    out.normal = vec4<f32>(FLAT_NORMAL, out.color.a);
+    out.emissive = vec4<f32>(0.0, 0.0, 0.0, out.color.a);
+    return out;
+}
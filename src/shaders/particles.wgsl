@@ -0,0 +1,85 @@
+struct Particle {
+    pos: vec2<f32>,
+    vel: vec2<f32>,
+    age: f32,
+    lifetime: f32,
+}
+
+struct Emitter {
+    pos: vec2<f32>,
+    velocity: vec2<f32>,
+    velocity_spread: vec2<f32>,
+    gravity: vec2<f32>,
+    color_start: vec4<f32>,
+    color_end: vec4<f32>,
+    size_start: f32,
+    size_end: f32,
+    lifetime: f32,
+    dt: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    capacity: u32,
+    seed: u32,
+    depth: f32,
+}
+
+struct Camera {
+    pos: vec2<f32>,
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> emitter: Emitter;
+@group(1) @binding(1) var<storage, read> particles: array<Particle>;
+
+var<private> quad_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(1.0, 1.0)
+);
+
+struct VSOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32,
+           @builtin(instance_index) ii: u32) -> VSOut {
+    let quad = quad_positions[vi];
+    let particle = particles[ii];
+
+    var out: VSOut;
+    if (particle.age >= particle.lifetime) {
+        // Dead particles collapse to a degenerate triangle
+        out.pos = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+        return out;
+    }
+
+    let life = particle.age / particle.lifetime;
+    let size = mix(emitter.size_start, emitter.size_end, life);
+    let pixel_pos = particle.pos + (quad - 0.5) * size;
+
+    let norm = (pixel_pos - camera.pos) / camera.size;
+
+    out.pos = vec4<f32>(norm.x * 2.0 - 1.0, 1.0 - norm.y * 2.0, emitter.depth, 1.0);
+    out.color = mix(emitter.color_start, emitter.color_end, life);
+    out.uv = quad;
+
+    return out;
+}
+
+@fragment
+fn fs_main(input: VSOut) -> GBufferOut {
+    let edge = 1.0 - smoothstep(0.8, 1.0, length(input.uv * 2.0 - 1.0));
+
+    var out: GBufferOut;
+    out.color = vec4<f32>(input.color.rgb, input.color.a * edge);
+    out.normal = vec4<f32>(FLAT_NORMAL, out.color.a);
+    out.emissive = vec4<f32>(0.0, 0.0, 0.0, out.color.a);
+    return out;
+}
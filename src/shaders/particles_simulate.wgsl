@@ -0,0 +1,70 @@
+struct Particle {
+    pos: vec2<f32>,
+    vel: vec2<f32>,
+    age: f32,
+    lifetime: f32,
+}
+
+struct Emitter {
+    pos: vec2<f32>,
+    velocity: vec2<f32>,
+    velocity_spread: vec2<f32>,
+    gravity: vec2<f32>,
+    color_start: vec4<f32>,
+    color_end: vec4<f32>,
+    size_start: f32,
+    size_end: f32,
+    lifetime: f32,
+    dt: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    capacity: u32,
+    seed: u32,
+    depth: f32,
+}
+
+@group(0) @binding(0) var<uniform> emitter: Emitter;
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+
+// PCG hash, good enough for spawn jitter
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random2(index: u32) -> vec2<f32> {
+    let a = hash(index ^ hash(emitter.seed));
+    let b = hash(a);
+    return vec2<f32>(f32(a), f32(b)) / 4294967295.0;
+}
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= emitter.capacity) {
+        return;
+    }
+
+    // Spawned particles occupy the ring slots after `spawn_start`
+    let slot = (i + emitter.capacity - emitter.spawn_start) % emitter.capacity;
+    if (slot < emitter.spawn_count) {
+        let jitter = random2(i) * 2.0 - 1.0;
+        var particle: Particle;
+        particle.pos = emitter.pos;
+        particle.vel = emitter.velocity + jitter * emitter.velocity_spread;
+        particle.age = 0.0;
+        particle.lifetime = emitter.lifetime;
+        particles[i] = particle;
+        return;
+    }
+
+    var particle = particles[i];
+    if (particle.age >= particle.lifetime) {
+        return;
+    }
+    particle.vel += emitter.gravity * emitter.dt;
+    particle.pos += particle.vel * emitter.dt;
+    particle.age += emitter.dt;
+    particles[i] = particle;
+}
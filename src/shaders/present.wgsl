@@ -0,0 +1,59 @@
+// Shows the composited scene in the surface's viewport, with bloom added on top. Tonemapping
+// and the sRGB encoding come last, after everything adding light.
+
+struct Params {
+    tonemap: u32,
+    encode_srgb: u32,
+};
+
+@group(0) @binding(0) var scene: texture_2d<f32>;
+// Nearest or linear, following the upscale mode
+@group(0) @binding(1) var scene_sampler: sampler;
+// Blurred bright pixels, black without bloom
+@group(0) @binding(2) var bloom: texture_2d<f32>;
+@group(0) @binding(3) var bloom_sampler: sampler;
+@group(0) @binding(4) var<uniform> params: Params;
+
+struct VSOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Fullscreen triangle, the scene's rows are already in the surface's order
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> VSOut {
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    var out: VSOut;
+    out.pos = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// Narkowicz's fit of the ACES filmic curve, maps HDR colors into 0..1
+fn tonemap_aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VSOut) -> @location(0) vec4<f32> {
+    var color = textureSample(scene, scene_sampler, in.uv);
+    color = vec4(color.rgb + textureSample(bloom, bloom_sampler, in.uv).rgb, color.a);
+    if (params.tonemap != 0u) {
+        color = vec4(tonemap_aces(color.rgb), color.a);
+    }
+    if (params.encode_srgb != 0u) {
+        color = vec4(linear_to_srgb(color.rgb), color.a);
+    }
+    return color;
+}
@@ -0,0 +1,57 @@
+// Resolves the shared multisampled attachments into one G-buffer layer. Colors are
+// averaged over the samples, ids and depth cannot be averaged and take the first one.
+// Colors and emissive hold straight alpha, so their rgb is weighted by each sample's alpha
+// and divided by the total again. Uncovered samples are transparent black and would
+// otherwise darken the edges, which the composite then blends by alpha a second time.
+
+override SAMPLE_COUNT: u32 = 4u;
+
+@group(0) @binding(0) var color: texture_multisampled_2d<f32>;
+@group(0) @binding(1) var normal: texture_multisampled_2d<f32>;
+@group(0) @binding(2) var emissive: texture_multisampled_2d<f32>;
+@group(0) @binding(3) var ids: texture_multisampled_2d<u32>;
+// Bound as plain floats, GLSL has no loads from depth textures
+@group(0) @binding(4) var depth: texture_multisampled_2d<f32>;
+
+struct FSOut {
+    @location(0) color: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) emissive: vec4<f32>,
+    @location(3) id: u32,
+    @builtin(frag_depth) depth: f32,
+};
+
+// Single triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn premultiply(sample: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(sample.rgb * sample.a, sample.a);
+}
+
+fn unpremultiply(average: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(average.rgb / max(average.a, 1e-5), average.a);
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> FSOut {
+    let coord = vec2<u32>(pos.xy);
+
+    var out: FSOut;
+    for (var i = 0u; i < SAMPLE_COUNT; i++) {
+        out.color += premultiply(textureLoad(color, coord, i));
+        out.normal += textureLoad(normal, coord, i);
+        out.emissive += premultiply(textureLoad(emissive, coord, i));
+    }
+    let weight = 1.0 / f32(SAMPLE_COUNT);
+    out.color = unpremultiply(out.color * weight);
+    out.normal *= weight;
+    out.emissive = unpremultiply(out.emissive * weight);
+
+    out.id = textureLoad(ids, coord, 0).r;
+    out.depth = textureLoad(depth, coord, 0).r;
+    return out;
+}
@@ -0,0 +1,103 @@
+// Signed distance field of the occluder layers by jump flooding. Seeds hold the nearest
+// pixel inside an occluder in rg and the nearest one outside in ba, -1 until one is found.
+// `seed` starts every pixel off with itself, each `jump` looks `step` pixels away in eight
+// directions for closer seeds with the step halving every dispatch, and `resolve` turns
+// the seeds into distances.
+
+struct Params {
+    // Bit per occluder layer
+    occluders: u32,
+    step: i32,
+};
+
+// Distance without any occluder, still exact in f16
+const FAR: f32 = 16384.0;
+
+@group(0) @binding(0) var colors: texture_2d_array<f32>;
+@group(0) @binding(1) var seeds_in: texture_2d<f32>;
+@group(0) @binding(2) var seeds_out: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var sdf: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(4) var<uniform> params: Params;
+
+fn occluded(pixel: vec2<i32>) -> bool {
+    for (var layer = 0u; layer < textureNumLayers(colors); layer++) {
+        if ((params.occluders & (1u << layer)) != 0u
+            && textureLoad(colors, pixel, layer, 0).a > 0.5) {
+            return true;
+        }
+    }
+    return false;
+}
+
+@compute @workgroup_size(8, 8)
+fn seed(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(seeds_out);
+    if (any(id.xy >= size)) {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    let here = vec2<f32>(pixel);
+    let none = vec2<f32>(-1.0);
+
+    if (occluded(pixel)) {
+        textureStore(seeds_out, pixel, vec4<f32>(here, none));
+    } else {
+        textureStore(seeds_out, pixel, vec4<f32>(none, here));
+    }
+}
+
+// Seed closer to `pixel` of the two, either may be missing
+fn closest(pixel: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    if (b.x < 0.0) {
+        return a;
+    }
+    if (a.x < 0.0) {
+        return b;
+    }
+    let da = pixel - a;
+    let db = pixel - b;
+    return select(a, b, dot(db, db) < dot(da, da));
+}
+
+@compute @workgroup_size(8, 8)
+fn jump(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = vec2<i32>(textureDimensions(seeds_out));
+    let pixel = vec2<i32>(id.xy);
+    if (any(pixel >= size)) {
+        return;
+    }
+    let here = vec2<f32>(pixel);
+
+    var seeds = textureLoad(seeds_in, pixel, 0);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let other = pixel + vec2<i32>(x, y) * params.step;
+            if (any(other < vec2<i32>(0)) || any(other >= size)) {
+                continue;
+            }
+            let found = textureLoad(seeds_in, other, 0);
+            seeds = vec4<f32>(closest(here, seeds.xy, found.xy), closest(here, seeds.zw, found.zw));
+        }
+    }
+    textureStore(seeds_out, pixel, seeds);
+}
+
+@compute @workgroup_size(8, 8)
+fn resolve(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(sdf);
+    if (any(id.xy >= size)) {
+        return;
+    }
+    let pixel = vec2<i32>(id.xy);
+    let here = vec2<f32>(pixel);
+    let seeds = textureLoad(seeds_in, pixel, 0);
+
+    // Measured to the edge between the pixels rather than their centers
+    var distance = FAR;
+    if (all(seeds.xy == here)) {
+        distance = select(-FAR, 0.5 - length(seeds.zw - here), seeds.z >= 0.0);
+    } else if (seeds.x >= 0.0) {
+        distance = length(seeds.xy - here) - 0.5;
+    }
+    textureStore(sdf, pixel, vec4<f32>(distance, 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,70 @@
+// Polar shadow maps, one row per light casting hard shadows and one column per direction around it. Each texel
+// holds how far its ray gets through the occluder layers before hitting an opaque pixel,
+// as a fraction of the light's radius.
+
+struct Params {
+    // Bit per occluder layer
+    occluders: u32,
+};
+
+struct Camera {
+    pos: vec2<f32>,
+    size: vec2<f32>,
+}
+
+// Directions per light, the width of the shadow map
+override RESOLUTION: u32 = 1024u;
+
+const TAU: f32 = 6.28318530718;
+// Longest ray marched, in G-buffer pixels
+const MAX_STEPS: f32 = 2048.0;
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<storage, read> lights: array<Light>;
+@group(1) @binding(1) var colors: texture_2d_array<f32>;
+@group(1) @binding(2) var<uniform> params: Params;
+
+// Single triangle covering the whole shadow map
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vi << 1u) & 2u), f32(vi & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn occluded(pixel: vec2<i32>) -> bool {
+    for (var layer = 0u; layer < textureNumLayers(colors); layer++) {
+        if ((params.occluders & (1u << layer)) != 0u
+            && textureLoad(colors, pixel, layer, 0).a > 0.5) {
+            return true;
+        }
+    }
+    return false;
+}
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    let light_index = u32(pos.y);
+    if (light_index >= arrayLength(&lights)) {
+        return vec4<f32>(1.0);
+    }
+    let light = lights[light_index];
+
+    let angle = pos.x / f32(RESOLUTION) * TAU;
+    let size = vec2<f32>(textureDimensions(colors));
+    // Marched in G-buffer pixels, one step per pixel along the ray
+    let origin = (light.pos - camera.pos) / camera.size * size;
+    let ray = vec2<f32>(cos(angle), sin(angle)) * light.radius / camera.size * size;
+    let steps = clamp(ceil(length(ray)), 1.0, MAX_STEPS);
+
+    for (var i = 0.0; i < steps; i += 1.0) {
+        let t = i / steps;
+        let pixel = vec2<i32>(floor(origin + ray * t));
+        if (any(pixel < vec2<i32>(0)) || any(pixel >= vec2<i32>(size))) {
+            continue;
+        }
+        if (occluded(pixel)) {
+            return vec4<f32>(t);
+        }
+    }
+    return vec4<f32>(1.0);
+}
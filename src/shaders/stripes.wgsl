@@ -0,0 +1,11 @@
+struct Stripes {
+    count: f32,
+    strength: f32,
+};
+
+@group(2) @binding(0) var<uniform> stripes: Stripes;
+
+fn material(input: VSOut) -> vec4<f32> {
+    let band = step(0.5, fract(input.uv.x * stripes.count));
+    return vec4<f32>(input.color.rgb * (1.0 - band * stripes.strength), input.color.a);
+}
@@ -0,0 +1,6 @@
+@group(2) @binding(1) var material_sampler: sampler;
+@group(2) @binding(2) var material_texture: texture_2d<f32>;
+
+fn material(input: VSOut) -> vec4<f32> {
+    return textureSample(material_texture, material_sampler, input.uv) * input.color;
+}
@@ -0,0 +1,100 @@
+struct Camera {
+    pos: vec2<f32>,
+    size: vec2<f32>,
+}
+
+struct Globals {
+    camera: Camera,
+    time: f32,
+}
+
+struct Tilemap {
+    pos: vec2<f32>,
+    tile_size: vec2<f32>,
+    map_size: vec2<u32>,
+    tileset_size: vec2<u32>,
+    depth: f32,
+}
+
+struct TileAnimation {
+    frames: u32,
+    frame_time: f32,
+}
+
+const TILE_FLIP_X: u32 = 0x80000000u;
+const TILE_FLIP_Y: u32 = 0x40000000u;
+const TILE_ROTATE: u32 = 0x20000000u;
+const TILE_INDEX_MASK: u32 = 0x1fffffffu;
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var<uniform> tilemap: Tilemap;
+@group(1) @binding(1) var tiles: texture_2d<u32>;
+@group(1) @binding(2) var tileset_sampler: sampler;
+@group(1) @binding(3) var tileset: texture_2d<f32>;
+@group(1) @binding(4) var<storage, read> animations: array<TileAnimation>;
+
+var<private> quad_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(1.0, 1.0)
+);
+
+struct VSOut {
+    @builtin(position) pos: vec4<f32>,
+    // Position in tiles from the map origin
+    @location(0) tile_pos: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vi: u32) -> VSOut {
+    let map_size = vec2<f32>(tilemap.map_size);
+    let tile_pos = quad_positions[vi] * map_size;
+    let pixel_pos = tilemap.pos + tile_pos * tilemap.tile_size;
+
+    let norm = (pixel_pos - globals.camera.pos) / globals.camera.size;
+
+    var out: VSOut;
+    out.pos = vec4<f32>(norm.x * 2.0 - 1.0, 1.0 - norm.y * 2.0, tilemap.depth, 1.0);
+    out.tile_pos = tile_pos;
+
+    return out;
+}
+
+@fragment
+fn fs_main(input: VSOut) -> GBufferOut {
+    let coord = min(vec2<u32>(input.tile_pos), tilemap.map_size - 1u);
+    let tile = textureLoad(tiles, coord, 0).r;
+    if (tile == 0u) {
+        discard;
+    }
+
+    var index = (tile & TILE_INDEX_MASK) - 1u;
+    let animation = animations[index];
+    if (animation.frames > 1u) {
+        index += u32(globals.time / animation.frame_time) % animation.frames;
+    }
+
+    var local = fract(input.tile_pos);
+    if ((tile & TILE_ROTATE) != 0u) {
+        local = vec2<f32>(local.y, 1.0 - local.x);
+    }
+    if ((tile & TILE_FLIP_X) != 0u) {
+        local.x = 1.0 - local.x;
+    }
+    if ((tile & TILE_FLIP_Y) != 0u) {
+        local.y = 1.0 - local.y;
+    }
+
+    let cell = vec2<f32>(vec2<u32>(index % tilemap.tileset_size.x, index / tilemap.tileset_size.x));
+    let uv = (cell + local) / vec2<f32>(tilemap.tileset_size);
+
+    var out: GBufferOut;
+    // Explicit level, derivatives jump between neighbouring tiles
+    out.color = textureSampleLevel(tileset, tileset_sampler, uv, 0.0);
+    out.normal = vec4<f32>(FLAT_NORMAL, out.color.a);
+    out.emissive = vec4<f32>(0.0, 0.0, 0.0, out.color.a);
+    return out;
+}